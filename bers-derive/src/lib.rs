@@ -0,0 +1,355 @@
+//! Derives for `bers`: `Packet` generates `read` and `write` from the struct fields in order,
+//! `Encode` and `Decode` do the same for types nested inside packets.
+//!
+//! Fields are encoded with their `Encode`/`Decode` impls unless a `#[packet(...)]` attribute
+//! says otherwise:
+//!
+//! - `big_endian` for numbers, plain numbers are little endian.
+//! - `varint` for u32/i32/u64/i64, signed ones are zigzag encoded.
+//! - `prefix = "u16"` or `prefix = "u32"` for a LE length in front of a `Vec` or `String`,
+//!   plain ones have a varuint32 length.
+//! - `since = V1_20_30` and `before = V1_20_30` to only put the field on the wire for those
+//!   protocols, by name from `protocol::version` or as a number. Otherwise it reads as
+//!   `Default::default()`. Packets only.
+//!
+//! The packet ID goes on the struct: `#[packet(id = 0x81)]`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as Tokens;
+use quote::{quote, quote_spanned};
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Expr, Fields, LitInt, LitStr,
+    Result, Type,
+};
+
+#[proc_macro_derive(Packet, attributes(packet))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_packet(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Encode, attributes(packet))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_encode(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Decode, attributes(packet))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_decode(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+enum Prefix {
+    VarInt,
+    U16,
+    U32,
+}
+
+struct Field {
+    name: syn::Ident,
+    ty: Type,
+    big_endian: bool,
+    varint: bool,
+    prefix: Prefix,
+    since: Option<Tokens>,
+    before: Option<Tokens>,
+}
+
+impl Field {
+    fn gated(&self) -> bool {
+        self.since.is_some() || self.before.is_some()
+    }
+}
+
+fn version(expr: Expr) -> Result<Tokens> {
+    match &expr {
+        Expr::Path(path) if path.path.get_ident().is_some() => {
+            Ok(quote!(::bers::protocol::version::#path))
+        }
+        Expr::Path(_) | Expr::Lit(_) => Ok(quote!(#expr)),
+        _ => Err(Error::new(expr.span(), "expected a protocol version")),
+    }
+}
+
+fn fields(input: &DeriveInput) -> Result<Vec<Field>> {
+    let data = match &input.data {
+        Data::Struct(p) => p,
+        _ => return Err(Error::new(input.span(), "only structs can be derived")),
+    };
+    let named = match &data.fields {
+        Fields::Named(p) => &p.named,
+        Fields::Unit => return Ok(vec![]),
+        Fields::Unnamed(p) => return Err(Error::new(p.span(), "fields must be named")),
+    };
+    let mut fields = vec![];
+    for field in named {
+        let mut parsed = Field {
+            name: field.ident.clone().expect("named field"),
+            ty: field.ty.clone(),
+            big_endian: false,
+            varint: false,
+            prefix: Prefix::VarInt,
+            since: None,
+            before: None,
+        };
+        for attr in field.attrs.iter().filter(|p| p.path().is_ident("packet")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("big_endian") {
+                    parsed.big_endian = true;
+                } else if meta.path.is_ident("varint") {
+                    parsed.varint = true;
+                } else if meta.path.is_ident("prefix") {
+                    let prefix: LitStr = meta.value()?.parse()?;
+                    parsed.prefix = match prefix.value().as_str() {
+                        "varint" => Prefix::VarInt,
+                        "u16" => Prefix::U16,
+                        "u32" => Prefix::U32,
+                        _ => return Err(meta.error("expected \"varint\", \"u16\" or \"u32\"")),
+                    };
+                } else if meta.path.is_ident("since") {
+                    parsed.since = Some(version(meta.value()?.parse()?)?);
+                } else if meta.path.is_ident("before") {
+                    parsed.before = Some(version(meta.value()?.parse()?)?);
+                } else {
+                    return Err(meta.error("unknown packet attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        if parsed.big_endian && parsed.varint {
+            return Err(Error::new(
+                field.span(),
+                "a field can't be both big_endian and varint",
+            ));
+        }
+        fields.push(parsed);
+    }
+    Ok(fields)
+}
+
+// last path segment of the field type, e.g. `Vec` for `Vec<String>`
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Path(p) => p
+            .path
+            .segments
+            .last()
+            .map(|p| p.ident.to_string())
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+fn varint_type(field: &Field) -> Result<Tokens> {
+    match type_name(&field.ty).as_str() {
+        "u32" => Ok(quote!(::bers::encoding::VarU32)),
+        "i32" => Ok(quote!(::bers::encoding::VarI32)),
+        "u64" => Ok(quote!(::bers::encoding::VarU64)),
+        "i64" => Ok(quote!(::bers::encoding::VarI64)),
+        _ => Err(Error::new(
+            field.ty.span(),
+            "varint needs a u32, i32, u64 or i64 field",
+        )),
+    }
+}
+
+// wrapper type the field goes through on the wire, or None if it is encoded as is
+fn wrapper(field: &Field) -> Result<Option<Tokens>> {
+    let ty = &field.ty;
+    if field.big_endian {
+        return Ok(Some(quote!(::bers::encoding::Be<#ty>)));
+    }
+    if field.varint {
+        return varint_type(field).map(Some);
+    }
+    match (&field.prefix, type_name(ty).as_str()) {
+        (Prefix::VarInt, _) => Ok(None),
+        (Prefix::U16, "Vec") => Ok(Some(quote!(::bers::encoding::VecU16<#ty>))),
+        (Prefix::U32, "Vec") => Ok(Some(quote!(::bers::encoding::VecU32<#ty>))),
+        (Prefix::U32, "String") => Ok(Some(quote!(::bers::encoding::LeString))),
+        _ => Err(Error::new(
+            ty.span(),
+            "this prefix only applies to a Vec, or a String with \"u32\"",
+        )),
+    }
+}
+
+fn encode_field(field: &Field) -> Result<Tokens> {
+    let name = &field.name;
+    let value = if field.big_endian {
+        quote!(::bers::encoding::Be(self.#name))
+    } else if field.varint {
+        let var = varint_type(field)?;
+        quote!(#var(self.#name))
+    } else {
+        match (&field.prefix, wrapper(field)?) {
+            (_, None) => quote!(self.#name),
+            (Prefix::U16, _) => quote!(::bers::encoding::VecU16(self.#name.as_slice())),
+            (Prefix::U32, _) if type_name(&field.ty) == "String" => {
+                quote!(::bers::encoding::LeString(self.#name.as_str()))
+            }
+            _ => quote!(::bers::encoding::VecU32(self.#name.as_slice())),
+        }
+    };
+    Ok(quote_spanned!(field.ty.span()=>
+        ::bers::encoding::Encode::encode(&#value, cursor)?;
+    ))
+}
+
+fn decode_field(field: &Field) -> Result<Tokens> {
+    let ty = &field.ty;
+    Ok(match wrapper(field)? {
+        Some(wrapper) => quote_spanned!(ty.span()=>
+            <#wrapper as ::bers::encoding::Decode>::decode(cursor)?.0
+        ),
+        None => quote_spanned!(ty.span()=>
+            <#ty as ::bers::encoding::Decode>::decode(cursor)?
+        ),
+    })
+}
+
+fn condition(field: &Field) -> Tokens {
+    match (&field.since, &field.before) {
+        (Some(since), Some(before)) => quote!((#since..#before).contains(&protocol)),
+        (Some(since), None) => quote!(protocol >= #since),
+        (None, Some(before)) => quote!(protocol < #before),
+        (None, None) => quote!(true),
+    }
+}
+
+fn no_gates(fields: &[Field]) -> Result<()> {
+    match fields.iter().find(|p| p.gated()) {
+        Some(field) => Err(Error::new(
+            field.name.span(),
+            "since and before only apply to packets",
+        )),
+        None => Ok(()),
+    }
+}
+
+fn construct(input: &DeriveInput, values: Vec<Tokens>, fields: &[Field]) -> Tokens {
+    let names = fields.iter().map(|p| &p.name);
+    match &input.data {
+        Data::Struct(p) if matches!(p.fields, Fields::Unit) => quote!(Self),
+        _ => quote!(Self { #(#names: #values,)* }),
+    }
+}
+
+fn expand_packet(input: &DeriveInput) -> Result<Tokens> {
+    let mut id = None;
+    for attr in input.attrs.iter().filter(|p| p.path().is_ident("packet")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse::<LitInt>()?);
+                Ok(())
+            } else {
+                Err(meta.error("unknown packet attribute"))
+            }
+        })?;
+    }
+    let id = match id {
+        Some(p) => p,
+        None => {
+            return Err(Error::new(
+                input.ident.span(),
+                "missing #[packet(id = ...)]",
+            ))
+        }
+    };
+    let fields = fields(input)?;
+
+    let mut reads = vec![];
+    let mut writes = vec![];
+    for field in &fields {
+        let read = decode_field(field)?;
+        let write = encode_field(field)?;
+        if !field.gated() {
+            reads.push(read);
+            writes.push(write);
+        } else {
+            let condition = condition(field);
+            reads.push(quote! {
+                if #condition { #read } else { ::std::default::Default::default() }
+            });
+            writes.push(quote!(if #condition { #write }));
+        }
+    }
+    let construct = construct(input, reads, &fields);
+    let protocol = if fields.iter().any(Field::gated) {
+        quote!(protocol)
+    } else {
+        quote!(_protocol)
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::bers::protocol::packets::Packet for #name #ty_generics #where_clause {
+            const ID: u16 = #id;
+
+            fn read(
+                cursor: &mut ::bers::reader::Reader,
+                #protocol: u32,
+            ) -> ::bers::reader::Result<Self> {
+                Ok(#construct)
+            }
+
+            fn write(&self, #protocol: u32) -> ::std::io::Result<::std::vec::Vec<u8>> {
+                let mut writer = ::bers::writer::Writer::new(::std::vec::Vec::new());
+                let cursor = &mut writer;
+                #(#writes)*
+                let _ = cursor;
+                Ok(writer.get_raw_payload())
+            }
+        }
+    })
+}
+
+fn expand_encode(input: &DeriveInput) -> Result<Tokens> {
+    let fields = fields(input)?;
+    no_gates(&fields)?;
+    let writes = fields
+        .iter()
+        .map(encode_field)
+        .collect::<Result<Vec<_>>>()?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::bers::encoding::Encode for #name #ty_generics #where_clause {
+            fn encode(&self, cursor: &mut ::bers::writer::Writer) -> ::std::io::Result<()> {
+                #(#writes)*
+                let _ = cursor;
+                Ok(())
+            }
+        }
+    })
+}
+
+fn expand_decode(input: &DeriveInput) -> Result<Tokens> {
+    let fields = fields(input)?;
+    no_gates(&fields)?;
+    let reads = fields
+        .iter()
+        .map(decode_field)
+        .collect::<Result<Vec<_>>>()?;
+    let construct = construct(input, reads, &fields);
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::bers::encoding::Decode for #name #ty_generics #where_clause {
+            fn decode(cursor: &mut ::bers::reader::Reader) -> ::bers::reader::Result<Self> {
+                let _ = &cursor;
+                Ok(#construct)
+            }
+        }
+    })
+}
@@ -1,6 +1,10 @@
 use std::net::SocketAddr;
 
-use bers::{motd::Motd, server::Listener};
+use bers::{
+    motd::Motd,
+    protocol::packets::Header,
+    server::{Event, Listener},
+};
 
 #[tokio::main]
 async fn main() {
@@ -15,10 +19,31 @@ async fn main() {
         game_mode: "Survival".to_owned(),
     };
     let local: SocketAddr = "127.0.0.1:19132".parse().expect("could not parse addr");
-    let mut server = Listener::new(motd, local).await;
-    server.listen().await;
+    let mut server = Listener::new(motd, local).await.expect("could not bind");
+    server.listen().await.expect("could not listen");
     loop {
-        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-        server.recieve().await;
+        let event = tokio::select! {
+            event = server.recv() => match event {
+                Some(p) => p,
+                None => break,
+            },
+            _ = tokio::signal::ctrl_c() => {
+                server.shutdown("Server closed").await;
+                break;
+            }
+        };
+        match event {
+            Event::Connected(session) => {
+                println!("{} joined", session.identity().display_name);
+            }
+            Event::Packet(id, payload) => {
+                if let Ok((header, _)) = Header::read(&payload) {
+                    println!("{:?} sent packet ID {}", id, header.id);
+                }
+            }
+            Event::Disconnected(id, reason, message) => {
+                println!("{:?} left : {:?} {}", id, reason, message);
+            }
+        }
     }
 }
@@ -0,0 +1,241 @@
+use std::{
+    collections::VecDeque,
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use raknet::RaknetEvent;
+
+use crate::{
+    codec::Codec,
+    protocol::{
+        compression::Compression,
+        crypto::{ecdsa::KeyPair, error::CryptErr},
+        login::{
+            exchange::client_exchange,
+            offline::{client_data_jwt, self_signed_jwt},
+        },
+        packets::{
+            client_to_server_handshake::Client2ServerHandshake,
+            decode,
+            disconnect::{Disconnect, DisconnectReason},
+            encode,
+            login_packet::LoginPacket,
+            network_settings::NetworkSettings,
+            play_status::PlayStatus,
+            request_network_settings::RequestNetworkSettings,
+            server_to_client_handshake::Server2ClientHandshake,
+            Header, Packet,
+        },
+        types::player_data::{ExtraData, PlayerData},
+        version,
+    },
+    server::raknet_error,
+};
+
+// how long to wait before asking RakNet again after it had nothing for us
+const IDLE_POLL: Duration = Duration::from_millis(10);
+
+fn crypt_error(e: CryptErr) -> Error {
+    Error::new(ErrorKind::InvalidData, e.to_string())
+}
+
+/// What the client logs in as.
+pub struct Identity {
+    pub keypair: KeyPair,
+    /// Login chain JWTs, the last one certifying `keypair`.
+    pub chain: Vec<String>,
+    /// Signed with `keypair` and sent as the client data JWT.
+    pub client_data: PlayerData,
+}
+
+impl Identity {
+    /// A self-signed identity, accepted by servers running `Auth::Offline`.
+    pub fn offline(
+        keypair: KeyPair,
+        extra_data: &ExtraData,
+        client_data: PlayerData,
+    ) -> Result<Self> {
+        let chain = vec![self_signed_jwt(&keypair, extra_data).map_err(crypt_error)?];
+        Ok(Self {
+            keypair,
+            chain,
+            client_data,
+        })
+    }
+}
+
+pub enum Event {
+    /// A game packet from the server, header included, ready for `protocol::packets::decode`.
+    Packet(Vec<u8>),
+    /// The server kicked us or the connection dropped. Sent once; `recv` returns `None` after it.
+    Disconnected(DisconnectReason, String),
+}
+
+pub struct Client {
+    socket: raknet::Client,
+    codec: Codec,
+    received: VecDeque<Vec<u8>>,
+    closed: bool,
+    disconnected: bool,
+}
+
+impl Client {
+    /// Connects and logs in with `protocol`. Returns once the server sent `LoginSuccess`,
+    /// with encryption enabled in both directions.
+    pub async fn connect(address: SocketAddr, identity: &Identity, protocol: u32) -> Result<Self> {
+        let socket = raknet::Client::connect(address)
+            .await
+            .map_err(raknet_error)?;
+        let mut client = Self {
+            socket,
+            codec: Codec::new(protocol),
+            received: VecDeque::new(),
+            closed: false,
+            disconnected: false,
+        };
+        client.login(identity).await?;
+        Ok(client)
+    }
+
+    async fn login(&mut self, identity: &Identity) -> Result<()> {
+        let protocol = self.codec.protocol;
+        if protocol >= version::V1_19_30 {
+            self.send(RequestNetworkSettings {
+                protocol_version: protocol,
+            })
+            .await?;
+            loop {
+                let payload = self.next_packet().await?;
+                match Header::read(&payload)?.0.id {
+                    NetworkSettings::ID => {
+                        let settings = decode::<NetworkSettings>(&payload, protocol)?;
+                        self.codec.compression = Some(settings.compression);
+                        self.codec.compression_threshold = settings.compression_threshold;
+                        break;
+                    }
+                    Disconnect::ID => return Err(refused(&payload, protocol)),
+                    _ => {}
+                }
+            }
+        } else {
+            self.codec.compression = Some(Compression::Zlib);
+        }
+
+        let chain = serde_json::json!({ "chain": identity.chain }).to_string();
+        let player_data =
+            client_data_jwt(&identity.keypair, &identity.client_data).map_err(crypt_error)?;
+        self.send(LoginPacket {
+            protocol_version: protocol,
+            chain,
+            player_data,
+        })
+        .await?;
+
+        loop {
+            let payload = self.next_packet().await?;
+            match Header::read(&payload)?.0.id {
+                Server2ClientHandshake::ID => {
+                    let handshake = decode::<Server2ClientHandshake>(&payload, protocol)?;
+                    let cipher =
+                        client_exchange(&identity.keypair, &handshake.salt).map_err(crypt_error)?;
+                    self.codec.set_cipher(cipher);
+                    self.codec.enable_encryption();
+                    self.send(Client2ServerHandshake {}).await?;
+                }
+                PlayStatus::ID => match decode::<PlayStatus>(&payload, protocol)? {
+                    PlayStatus::LoginSuccess => return Ok(()),
+                    status => {
+                        return Err(Error::new(
+                            ErrorKind::ConnectionRefused,
+                            format!("login failed {:?}", status),
+                        ))
+                    }
+                },
+                Disconnect::ID => return Err(refused(&payload, protocol)),
+                _ => {} // nothing else is meaningful before login succeeds
+            }
+        }
+    }
+
+    /// Protocol number the client logged in with.
+    pub fn protocol(&self) -> u32 {
+        self.codec.protocol
+    }
+
+    pub async fn send<T: Packet>(&mut self, packet: T) -> Result<()> {
+        let buff = encode::<T>(packet, self.codec.protocol)?;
+        self.codec.queue(&buff)?;
+        if let Some(batch) = self.codec.flush()? {
+            self.socket.send(&batch).await.map_err(raknet_error)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the next packet from the server. Returns `None` once disconnected.
+    pub async fn recv(&mut self) -> Option<Event> {
+        if self.disconnected {
+            return None;
+        }
+        let payload = match self.next_packet().await {
+            Ok(p) => p,
+            Err(e) => {
+                self.disconnected = true;
+                return Some(Event::Disconnected(
+                    DisconnectReason::Disconnected,
+                    e.to_string(),
+                ));
+            }
+        };
+        if matches!(Header::read(&payload), Ok((header, _)) if header.id == Disconnect::ID) {
+            self.disconnected = true;
+            return Some(match decode::<Disconnect>(&payload, self.codec.protocol) {
+                Ok(p) => Event::Disconnected(p.reason, p.kick_message),
+                Err(_) => Event::Disconnected(DisconnectReason::Unknown, "".to_owned()),
+            });
+        }
+        Some(Event::Packet(payload))
+    }
+
+    pub async fn disconnect(mut self) -> Result<()> {
+        self.socket.disconnect().await.map_err(raknet_error)
+    }
+
+    async fn next_packet(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(payload) = self.received.pop_front() {
+                return Ok(payload);
+            }
+            if self.closed {
+                return Err(Error::new(
+                    ErrorKind::NotConnected,
+                    "connection closed".to_owned(),
+                ));
+            }
+            let events = self.socket.recv().await.map_err(raknet_error)?;
+            if events.is_empty() {
+                tokio::time::sleep(IDLE_POLL).await;
+            }
+            for event in events {
+                match event {
+                    RaknetEvent::Packet(p) => {
+                        let packets = self.codec.decode(p.data)?;
+                        self.received
+                            .extend(packets.into_iter().filter(|p| !p.is_empty()));
+                    }
+                    RaknetEvent::Disconnected(..) | RaknetEvent::Error(..) => self.closed = true,
+                    RaknetEvent::Connected(..) => {}
+                }
+            }
+        }
+    }
+}
+
+fn refused(payload: &[u8], protocol: u32) -> Error {
+    let message = match decode::<Disconnect>(payload, protocol) {
+        Ok(p) => p.kick_message,
+        Err(e) => e.to_string(),
+    };
+    Error::new(ErrorKind::ConnectionRefused, message)
+}
@@ -0,0 +1,227 @@
+use std::io::{Error, ErrorKind, Result};
+
+use crate::{
+    config::BatchLimits,
+    protocol::{compression::Compression, crypto::cipher::Cipher, version},
+    reader::{DecodeError, Reader},
+    writer::Writer,
+};
+
+const GAME_PACKET: u8 = 0xfe;
+
+fn frame(buff: &[u8]) -> Result<Vec<u8>> {
+    let mut cursor = Writer::new(vec![]);
+    cursor.write_varu32(buff.len() as u32)?;
+    cursor.write(buff)?;
+    Ok(cursor.get_raw_payload())
+}
+
+/// Game packet batches as they travel inside RakNet: `0xfe`, then the encrypted and compressed
+/// list of length-prefixed packets. Shared by the server connection and the client.
+pub struct Codec {
+    pub protocol: u32,
+    /// `None` until negotiated; batches are read and written uncompressed until then.
+    pub compression: Option<Compression>,
+    pub compression_threshold: u16,
+    /// Inbound batches breaking these fail to decode.
+    pub limits: BatchLimits,
+    cipher: Option<Cipher>,
+    encryption: bool,
+    send_queue: Vec<u8>,
+}
+
+impl Codec {
+    pub fn new(protocol: u32) -> Self {
+        Self {
+            protocol,
+            compression: None,
+            compression_threshold: 0,
+            limits: BatchLimits::default(),
+            cipher: None,
+            encryption: false,
+            send_queue: vec![],
+        }
+    }
+
+    /// Decrypts inbound batches from now on.
+    pub fn set_cipher(&mut self, cipher: Cipher) {
+        self.cipher = Some(cipher);
+    }
+
+    /// Encrypts outbound batches from now on. Needs a cipher.
+    pub fn enable_encryption(&mut self) {
+        self.encryption = self.cipher.is_some();
+    }
+
+    /// Appends an encoded packet, header included, to the next batch.
+    pub fn queue(&mut self, payload: &[u8]) -> Result<()> {
+        let mut framed = frame(payload)?;
+        self.send_queue.append(&mut framed);
+        Ok(())
+    }
+
+    /// Turns everything queued into one batch ready for RakNet.
+    pub fn flush(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.send_queue.is_empty() {
+            return Ok(None);
+        }
+        let queue = std::mem::take(&mut self.send_queue);
+        let mut batch = self.compress(&queue)?;
+
+        if self.encryption {
+            if let Some(cipher) = self.cipher.as_mut() {
+                if let Err(e) = cipher.encode(&mut batch) {
+                    return Err(Error::new(ErrorKind::InvalidData, e.to_string()));
+                }
+            }
+        }
+
+        batch.insert(0, GAME_PACKET);
+        Ok(Some(batch))
+    }
+
+    /// Splits a RakNet payload into its packets. Payloads that are not game packets yield nothing.
+    pub fn decode(&mut self, mut data: Vec<u8>) -> Result<Vec<Vec<u8>>> {
+        if data.first() != Some(&GAME_PACKET) {
+            return Ok(vec![]);
+        }
+        data.remove(0);
+
+        if let Some(cipher) = self.cipher.as_mut() {
+            if let Err(e) = cipher.decode(&mut data) {
+                return Err(Error::new(ErrorKind::InvalidData, e.to_string()));
+            }
+        }
+
+        let data = self.decompress(data)?;
+        let mut cursor = Reader::new(&data);
+
+        let mut packets = vec![];
+        while cursor.remaining() > 0 {
+            if packets.len() == self.limits.max_packets {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("batch has over {} packets", self.limits.max_packets),
+                ));
+            }
+            let length = cursor.read_varu32()? as usize;
+            if length > self.limits.max_packet_size {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("packet of {} bytes in batch", length),
+                ));
+            }
+            packets.push(cursor.read_bytes(length)?.to_vec());
+        }
+        Ok(packets)
+    }
+
+    fn decompress(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        let max_size = self.limits.max_batch_size;
+        let compression = match self.compression {
+            Some(p) => p,
+            None => return Compression::None.decompress(&data, max_size),
+        };
+        if self.protocol >= version::V1_20_60 {
+            // small batches go uncompressed, anything else has to be what was negotiated
+            match data.split_first() {
+                Some((prefix, body)) => match Compression::from_prefix(*prefix) {
+                    Some(p) if p == compression || p == Compression::None => {
+                        p.decompress(body, max_size)
+                    }
+                    Some(p) => Err(DecodeError::InvalidValue(format!(
+                        "{:?} batch with {:?} negotiated",
+                        p, compression
+                    ))
+                    .into()),
+                    None => Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "unknown compression prefix".to_owned(),
+                    )),
+                },
+                None => Ok(data),
+            }
+        } else {
+            compression.decompress(&data, max_size)
+        }
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let compression = match self.compression {
+            Some(p) => p,
+            None => return Ok(data.to_vec()),
+        };
+        if self.protocol >= version::V1_20_60 {
+            let compression = if data.len() < self.compression_threshold as usize {
+                Compression::None
+            } else {
+                compression
+            };
+            Ok([&[compression.prefix()], &*compression.compress(data)?].concat())
+        } else {
+            compression.compress(data)
+        }
+    }
+}
+
+#[test]
+fn codec() {
+    for protocol in [version::V1_18_0, version::V1_20_60] {
+        let mut codec = Codec::new(protocol);
+        codec.compression = Some(Compression::Zlib);
+        codec.queue(&[0x02, 0, 0, 0, 0]).unwrap();
+        codec.queue(&[0x81, 1]).unwrap();
+        let batch = codec.flush().unwrap().unwrap();
+        assert_eq!(codec.flush().unwrap(), None);
+        let packets = codec.decode(batch).unwrap();
+        assert_eq!(packets, vec![vec![0x02, 0, 0, 0, 0], vec![0x81, 1]]);
+    }
+}
+
+#[test]
+fn batch_limits() {
+    let mut codec = Codec::new(version::V1_20_60);
+    codec.compression = Some(Compression::Zlib);
+    codec.limits = BatchLimits {
+        max_batch_size: 1024,
+        max_packets: 2,
+        max_packet_size: 100,
+    };
+    let mut batch = |packets: &[&[u8]]| {
+        for packet in packets {
+            codec.queue(packet).unwrap();
+        }
+        let batch = codec.flush().unwrap().unwrap();
+        codec.decode(batch)
+    };
+    let error = |result: Result<Vec<Vec<u8>>>| result.unwrap_err().to_string();
+    assert!(batch(&[&[0x81, 1], &[0x81, 1]]).is_ok());
+    assert!(error(batch(&[&[0x81, 1], &[0x81, 1], &[0x81, 1]])).contains("over 2 packets"));
+    assert!(error(batch(&[&[0; 101]])).contains("101 bytes"));
+    assert!(batch(&[&[0; 100], &[0; 100]]).is_ok());
+    // compresses to a few bytes but inflates past the batch size
+    assert!(error(batch(&[&[0; 2000]])).contains("over 1024 bytes"));
+}
+
+#[test]
+fn negotiated_compression() {
+    let mut server = Codec::new(version::V1_20_60);
+    server.compression = Some(Compression::Zlib);
+    let mut batch = |compression, threshold| {
+        let mut client = Codec::new(version::V1_20_60);
+        client.compression = Some(compression);
+        client.compression_threshold = threshold;
+        client.queue(&[0x81, 1]).unwrap();
+        server.decode(client.flush().unwrap().unwrap())
+    };
+    assert!(batch(Compression::Zlib, 0).is_ok());
+    // under the threshold, sent uncompressed
+    assert!(batch(Compression::Zlib, 1024).is_ok());
+    let error = batch(Compression::Snappy, 0).unwrap_err();
+    assert!(matches!(
+        error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<DecodeError>()),
+        Some(DecodeError::InvalidValue(_))
+    ));
+}
@@ -0,0 +1,100 @@
+use crate::{
+    nbt::{Compound, Tag},
+    protocol::{
+        compression::Compression,
+        login::verify::Verifier,
+        packets::{creative_content::CreativeItem, start_game::StartGame},
+    },
+    rate_limit::RateLimits,
+    resource_pack::ResourcePack,
+};
+
+/// What players are sent between resource packs and spawning.
+#[derive(Clone, Debug)]
+pub struct Spawn {
+    /// Sent as is, with the player's entity IDs and game version filled in.
+    pub start_game: StartGame,
+    /// `BiomeDefinitionList`, biome names to their definitions.
+    pub biome_definitions: Tag,
+    /// `AvailableActorIdentifiers`, the entity list under `idlist`.
+    pub actor_identifiers: Tag,
+    pub creative_items: Vec<CreativeItem>,
+    /// Largest view distance granted to `RequestChunkRadius`, in chunks.
+    pub max_chunk_radius: i32,
+}
+
+impl Default for Spawn {
+    fn default() -> Self {
+        let mut player = Compound::new();
+        player.insert("id".to_owned(), Tag::String("minecraft:player".to_owned()));
+        player.insert("bid".to_owned(), Tag::String(String::new()));
+        player.insert("rid".to_owned(), Tag::Int(1));
+        player.insert("hasspawnegg".to_owned(), Tag::Byte(0));
+        player.insert("summonable".to_owned(), Tag::Byte(0));
+        player.insert("experimental".to_owned(), Tag::Byte(0));
+        let mut identifiers = Compound::new();
+        identifiers.insert("idlist".to_owned(), Tag::List(vec![Tag::Compound(player)]));
+        Self {
+            start_game: StartGame::default(),
+            biome_definitions: Tag::Compound(Compound::new()),
+            actor_identifiers: Tag::Compound(identifiers),
+            creative_items: vec![],
+            max_chunk_radius: 8,
+        }
+    }
+}
+
+/// Caps on what one inbound batch may unpack to, so a small datagram can't make the server
+/// allocate without bound. Connections going over them are kicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchLimits {
+    /// Bytes of a batch after decompression.
+    pub max_batch_size: usize,
+    pub max_packets: usize,
+    /// Bytes of a single packet, header included.
+    pub max_packet_size: usize,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 8 * 1024 * 1024,
+            max_packets: 512,
+            max_packet_size: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// Listener settings shared by every connection.
+#[derive(Clone)]
+pub struct Config {
+    /// Offered to clients from 1.19.30 on. Older clients always use zlib.
+    pub compression: Compression,
+    /// Batches smaller than this are sent uncompressed, from 1.20.60 on.
+    pub compression_threshold: u16,
+    pub batch_limits: BatchLimits,
+    /// Logins, packets and bytes clients may send, per session and per IP.
+    pub rate_limits: RateLimits,
+    /// Login chain checks: online or offline, trusted roots, clock skew.
+    pub login: Verifier,
+    /// Sent to every player before they join, see `ResourcePack::load_dir`.
+    pub resource_packs: Vec<ResourcePack>,
+    /// Players who refuse the packs are kicked.
+    pub require_packs: bool,
+    pub spawn: Spawn,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            compression: Compression::Zlib,
+            compression_threshold: 256,
+            batch_limits: BatchLimits::default(),
+            rate_limits: RateLimits::default(),
+            login: Verifier::default(),
+            resource_packs: vec![],
+            require_packs: false,
+            spawn: Spawn::default(),
+        }
+    }
+}
@@ -1,176 +1,951 @@
 use std::{
-    io::{Cursor, Read, Write},
+    collections::{HashMap, HashSet},
     net::SocketAddr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
 };
 
-use flate2::read::DeflateDecoder;
-use minecraft_varint::VarIntRead;
-use raknet::{packet::RaknetPacket, Server};
-use tokio::sync::Mutex;
+use tokio::{
+    sync::{
+        mpsc::{self, Receiver, UnboundedReceiver, UnboundedSender},
+        watch,
+    },
+    time::Instant,
+};
 
 use crate::{
+    codec::Codec,
+    config::Config,
     protocol::{
-        crypto::cipher::Cipher,
-        login::{
-            exchange::exchange,
-            verify::{self, verify_skin},
-        },
+        compression::Compression,
+        login::{exchange::exchange, verify::verify_skin},
         packets::{
-            client_to_server_handshake::Client2ServerHandshake, decode, encode,
-            login_packet::LoginPacket, server_to_client_handshake::Server2ClientHandshake, Packet, play_status::PlayStatus, resource_packs_info::ResourcePacksInfo,
+            available_actor_identifiers::AvailableActorIdentifiers,
+            biome_definition_list::BiomeDefinitionList,
+            chunk_radius_updated::ChunkRadiusUpdated,
+            client_cache_status::ClientCacheStatus,
+            client_to_server_handshake::Client2ServerHandshake,
+            creative_content::CreativeContent,
+            decode,
+            disconnect::{Disconnect, DisconnectReason},
+            encode_for,
+            login_packet::LoginPacket,
+            network_chunk_publisher_update::NetworkChunkPublisherUpdate,
+            network_settings::NetworkSettings,
+            play_status::PlayStatus,
+            request_chunk_radius::RequestChunkRadius,
+            request_network_settings::RequestNetworkSettings,
+            resource_pack_chunk_data::ResourcePackChunkData,
+            resource_pack_chunk_request::ResourcePackChunkRequest,
+            resource_pack_client_response::{ResourcePackClientResponse, ResponseStatus},
+            resource_pack_data_info::{PackType, ResourcePackDataInfo},
+            resource_pack_stack::ResourcePackStack,
+            resource_packs_info::ResourcePacksInfo,
+            server_to_client_handshake::Server2ClientHandshake,
+            set_local_player_as_initialized::SetLocalPlayerAsInitialized,
+            start_game::StartGame,
+            sub_client_login::SubClientLogin,
+            Header, Packet,
+        },
+        types::{
+            player_data::{ExtraData, PlayerData},
+            position::BlockPos,
         },
+        version::{self, VersionMismatch},
     },
-    writer::Writer,
+    rate_limit::{Action, Buckets, Kind, Limiter},
+    reader::Reader,
+    resource_pack::{ResourcePack, CHUNK_SIZE},
+    server::Event,
+    session::{Command, Session, SessionId},
 };
 
-const PROTOCOL_VERSION : u32 = 475;
+/// Login progress of a connection. Packets are only accepted in the state that expects them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// RakNet session is up, waiting for `RequestNetworkSettings`, or for `LoginPacket`
+    /// from clients older than 1.19.30.
+    Unconnected,
+    /// Compression negotiated, waiting for `LoginPacket`.
+    Login,
+    /// `Server2ClientHandshake` sent, waiting for the client to confirm encryption.
+    Handshake,
+    /// `ResourcePacksInfo` sent, waiting for the client to finish pack negotiation.
+    ResourcePacks,
+    /// Packs done, the world is being sent until the client reports it is initialized.
+    StartGame,
+    Spawned,
+}
+
+// `RequestNetworkSettings` is the only packet sent before compression is known, and it is
+// always alone in its batch.
+fn is_network_settings_request(batch: &[u8]) -> bool {
+    let mut cursor = Reader::new(batch);
+    match cursor.read_varu32() {
+        Ok(length) => {
+            length as usize == cursor.remaining()
+                && matches!(
+                    Header::read_from(&mut cursor),
+                    Ok(header) if header.id == RequestNetworkSettings::ID
+                )
+        }
+        Err(_) => false,
+    }
+}
+
+// runtime and unique entity IDs handed out in `StartGame`, unique across connections
+static NEXT_ENTITY_ID: AtomicU64 = AtomicU64::new(1);
+
+fn find_pack<'a>(packs: &'a [ResourcePack], id: &str) -> Option<&'a ResourcePack> {
+    packs.iter().find(|p| p.matches(id))
+}
+
+// a throttled batch, held before decoding when the socket task throttled it, after when
+// its packets did
+enum Held {
+    Batch(Vec<u8>),
+    Packets(Vec<Vec<u8>>),
+}
+
+/// Player slots shared by every connection. A slot is held from a verified login until disconnect.
+pub struct Slots {
+    online: AtomicU32,
+    max: u32,
+}
+
+impl Slots {
+    pub fn new(max: u32) -> Self {
+        Self {
+            online: AtomicU32::new(0),
+            max,
+        }
+    }
+
+    pub fn online(&self) -> u32 {
+        self.online.load(Ordering::SeqCst)
+    }
+
+    fn take(&self) -> bool {
+        self.online
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n < self.max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
 
-fn get_varint(value: u32) -> std::io::Result<Vec<u8>> {
-    let mut cursor = Writer::new(vec![]);
-    cursor.write_varu32(value)?;
-    Ok(cursor.get_raw_payload())
+    fn release(&self) {
+        self.online.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
-fn frame<T: Packet>(p: T) -> std::io::Result<Vec<u8>> {
-    let buff = encode::<T>(p)?;
-    let payload = get_varint(buff.len() as u32)?;
-    Ok([&payload, &*buff].concat())
+/// Batches a connection may have queued before the socket task drops new ones.
+pub const INBOUND_QUEUE: usize = 64;
+
+/// From the socket task to a connection task.
+pub enum Inbound {
+    /// A batch as received, to be handled once the instant passed if it was throttled.
+    Packet(Vec<u8>, Option<Instant>),
+    /// A batch went over a rate limit the socket task checks and was dropped.
+    OverLimit(Kind),
+    Disconnected(DisconnectReason, String),
+}
+
+/// From a connection task to the socket task.
+pub enum Outbound {
+    Send(SocketAddr, Vec<u8>),
+    Close(SocketAddr),
+    /// A slot was taken or released, the MOTD needs updating.
+    PlayerCount,
 }
 
 pub struct Connection {
-    socket: Arc<Mutex<Server>>,
     address: SocketAddr,
-    send_queue: Vec<u8>,
-    encryption : bool,
-    cipher: Option<Cipher>,
+    /// Protocol is assumed to be the latest until the client tells us.
+    codec: Codec,
+    events: UnboundedSender<Event>,
+    outbound: UnboundedSender<Outbound>,
+    slots: Arc<Slots>,
+    has_slot: bool,
+    config: Arc<Config>,
+    limiter: Arc<Limiter>,
+    buckets: Buckets,
+    /// A throttled batch and when it may be handled. Nothing else is received until then.
+    held: Option<(Instant, Held)>,
+    commands: UnboundedSender<Command>,
+    command_receiver: Option<UnboundedReceiver<Command>>,
+    login: Option<(ExtraData, PlayerData)>,
+    /// Split-screen players logged in on this connection, each holding a slot.
+    sub_clients: Vec<u8>,
+    state: State,
+    connected: bool,
+    closed: bool,
+    close_session: bool,
+    /// Chunks sent of each pack, by pack ID. Every chunk is only sent once.
+    sent_chunks: HashMap<String, HashSet<u32>>,
+    /// Entity ID the player got in `StartGame`.
+    entity_id: u64,
+    /// View distance granted to the last `RequestChunkRadius`.
+    chunk_radius: Option<i32>,
 }
 
 impl Connection {
-    pub fn new(socket: Arc<Mutex<Server>>, address: SocketAddr) -> Self {
+    pub fn new(
+        address: SocketAddr,
+        events: UnboundedSender<Event>,
+        outbound: UnboundedSender<Outbound>,
+        slots: Arc<Slots>,
+        config: Arc<Config>,
+        limiter: Arc<Limiter>,
+    ) -> Self {
+        let (commands, command_receiver) = mpsc::unbounded_channel();
+        let mut codec = Codec::new(version::LATEST);
+        codec.compression_threshold = config.compression_threshold;
+        codec.limits = config.batch_limits;
         Self {
-            socket,
             address,
-            send_queue: vec![],
-            encryption : false,
-            cipher: None,
+            codec,
+            events,
+            outbound,
+            slots,
+            has_slot: false,
+            config,
+            buckets: limiter.session(),
+            limiter,
+            held: None,
+            commands,
+            command_receiver: Some(command_receiver),
+            login: None,
+            sub_clients: vec![],
+            state: State::Unconnected,
+            connected: false,
+            closed: false,
+            close_session: false,
+            sent_chunks: HashMap::new(),
+            entity_id: 0,
+            chunk_radius: None,
         }
     }
-    pub fn handle(&mut self, mut packet: RaknetPacket) {
-        if packet.data.remove(0) != 0xfe {
-            return;
-        }
-
-        if self.cipher.is_some() {
-            match self.cipher.as_mut().unwrap().decode(&mut packet.data) {
-                Ok(_) => {}
-                Err(e) => {
-                    if let crate::protocol::crypto::error::CryptErr::BadPacket = e {
-                        self.bad_packet();
+    /// Drives the connection until it is closed. Everything already queued is handled before
+    /// the outbound batch is flushed, so a burst of packets goes out as one batch. `shutdown`
+    /// gets the kick message once the server shuts down.
+    pub async fn run(
+        mut self,
+        mut inbound: Receiver<Inbound>,
+        mut shutdown: watch::Receiver<Option<String>>,
+    ) {
+        let mut commands = match self.command_receiver.take() {
+            Some(p) => p,
+            None => return,
+        };
+        loop {
+            let held = self.held.as_ref().map(|p| p.0);
+            tokio::select! {
+                biased;
+                // before the held batch, a throttled connection is kicked without waiting for it
+                Ok(()) = shutdown.changed() => {
+                    if let Some(message) = shutdown.borrow_and_update().clone() {
+                        self.kick(DisconnectReason::Shutdown, &message);
                     }
                 }
-            };
+                message = inbound.recv(), if held.is_none() => match message {
+                    Some(message) => self.inbound(message),
+                    // the socket task forgot the session, its `Disconnected` may not have fit
+                    // in the queue
+                    None => self.disconnected(DisconnectReason::ConnectionLost, "connection closed"),
+                },
+                Some(command) = commands.recv() => self.command(command),
+                _ = tokio::time::sleep_until(held.unwrap_or_else(Instant::now)), if held.is_some() => {
+                    match self.held.take() {
+                        Some((_, Held::Batch(data))) => self.handle(data),
+                        Some((_, Held::Packets(packets))) => self.handle_packets(packets),
+                        None => {}
+                    }
+                }
+                else => break,
+            }
+            while self.held.is_none() {
+                match inbound.try_recv() {
+                    Ok(message) => self.inbound(message),
+                    Err(_) => break,
+                }
+            }
+            while let Ok(command) = commands.try_recv() {
+                self.command(command);
+            }
+            self.flush();
+            if self.closed {
+                break;
+            }
         }
+    }
 
-        let mut decompressor = DeflateDecoder::new(&*packet.data);
+    fn inbound(&mut self, message: Inbound) {
+        match message {
+            Inbound::Packet(data, Some(ready)) if ready > Instant::now() => {
+                self.held = Some((ready, Held::Batch(data)));
+            }
+            Inbound::Packet(data, _) => self.handle(data),
+            Inbound::OverLimit(kind) => self.over_limit(kind),
+            Inbound::Disconnected(reason, message) => self.disconnected(reason, &message),
+        }
+    }
 
-        // deflate packet
-        let mut data = vec![];
+    fn command(&mut self, command: Command) {
+        match command {
+            Command::Send(buff) => self.send_raw(&buff),
+            Command::Kick(0, reason, message) => self.kick(reason, &message),
+            Command::Kick(sub_client, reason, message) => {
+                self.kick_sub_client(sub_client, reason, &message)
+            }
+        }
+    }
+
+    pub fn handle(&mut self, data: Vec<u8>) {
+        if self.closed {
+            return;
+        }
 
-        match decompressor.read_to_end(&mut data) {
+        if self.codec.compression.is_none()
+            && !is_network_settings_request(data.get(1..).unwrap_or_default())
+        {
+            // clients older than 1.19.30 compress with zlib from their first packet
+            self.codec.compression = Some(Compression::Zlib);
+        }
+
+        let packets = match self.codec.decode(data) {
             Ok(p) => p,
             Err(e) => {
-                eprintln!("decompressing error {}", e);
+                eprintln!("bad batch {} from {}", e, self.address);
+                self.bad_packet();
                 return;
             }
         };
 
-        let mut cursor = Cursor::new(&data);
-
-        // split packet
-        let mut packets: Vec<&[u8]> = vec![];
-        while cursor.position() < data.len() as u64 {
-            let length = match cursor.read_var_u32() {
-                Ok(p) => p,
-                Err(e) => {
-                    eprintln!("{}", e);
-                    break;
-                }
-            };
+        if let Some(packets) = self.rate_limit(packets) {
+            self.handle_packets(packets);
+        }
+    }
 
-            if cursor.position() + length as u64 > data.len() as u64 {
-                break;
+    /// Checks the logins and packets of a batch against the rate limits, its bytes were
+    /// checked by the socket task before decoding. Returns the packets if they can be handled
+    /// now; throttled ones are held until `run` gets back to them.
+    fn rate_limit(&mut self, packets: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+        let logins = packets
+            .iter()
+            .filter(|p| {
+                matches!(Header::read(p), Ok((header, _))
+                    if header.id == LoginPacket::ID || header.id == SubClientLogin::ID)
+            })
+            .count();
+        let amounts = [logins as u64, packets.len() as u64, 0];
+        let now = Instant::now();
+        match self
+            .limiter
+            .take(&mut self.buckets, self.address.ip(), amounts, now)
+        {
+            Ok(wait) if wait.is_zero() => Some(packets),
+            Ok(wait) => {
+                self.held = Some((now + wait, Held::Packets(packets)));
+                None
             }
+            Err(kind) => {
+                self.over_limit(kind);
+                None
+            }
+        }
+    }
 
-            let packet =
-                &data[cursor.position() as usize..(cursor.position() + length as u64) as usize];
-            packets.push(packet);
-
-            cursor.set_position(cursor.position() + length as u64);
+    /// Runs the configured action for a dropped batch.
+    fn over_limit(&mut self, kind: Kind) {
+        let action = self.limiter.limits.action;
+        eprintln!(
+            "{} went over the {:?} rate limit, {:?}",
+            self.address, kind, action
+        );
+        match action {
+            Action::Drop | Action::Throttle => {}
+            Action::Kick => self.kick(DisconnectReason::Kicked, "Sending too fast"),
+            Action::Ban(duration) => {
+                self.limiter.ban(self.address.ip(), duration);
+                self.kick(DisconnectReason::Kicked, "Sending too fast");
+            }
         }
+    }
 
+    fn handle_packets(&mut self, packets: Vec<Vec<u8>>) {
         for packet in packets {
-            self.handle_packet(packet);
+            if self.closed {
+                break;
+            }
+            if packet.is_empty() {
+                continue;
+            }
+            self.handle_packet(&packet);
         }
     }
+
     pub fn handle_packet(&mut self, payload: &[u8]) {
-        // decode packet
-        match payload[0] {
-            LoginPacket::ID => {
+        let header = match Header::read(payload) {
+            Ok((p, _)) => p,
+            Err(e) => {
+                eprintln!("bad packet header {} from {}", e, self.address);
+                self.bad_packet();
+                return;
+            }
+        };
+        if header.sender != 0 {
+            self.handle_sub_client(header, payload);
+            return;
+        }
+        match (self.state, header.id) {
+            (State::Unconnected, RequestNetworkSettings::ID) => {
+                self.handle_network_settings(payload);
+            }
+            (State::Unconnected | State::Login, LoginPacket::ID) => {
+                self.state = State::Login;
                 self.handle_login(payload);
             }
-            Client2ServerHandshake::ID => {
-                self.encryption = true;
+            (State::Handshake, Client2ServerHandshake::ID) => {
+                self.codec.enable_encryption();
 
-                let play_satus = PlayStatus::LoginSuccess;
+                let resource_info = self.packs_info();
+                if !(self.send_or_kick(PlayStatus::LoginSuccess)
+                    && self.send_or_kick(resource_info))
+                {
+                    return;
+                }
+                self.state = State::ResourcePacks;
 
-                self.send(play_satus).unwrap();
+                if let Some((identity, client_data)) = self.login.clone() {
+                    let session = Session::new(
+                        self.session_id(0),
+                        self.codec.protocol,
+                        identity,
+                        client_data,
+                        self.commands.clone(),
+                    );
+                    self.connected = true;
+                    let _ = self.events.send(Event::Connected(session));
+                }
+            }
+            (State::ResourcePacks, ResourcePackClientResponse::ID) => {
+                self.handle_pack_response(payload);
             }
-            0x81 => {
-                let resource_info = ResourcePacksInfo{ force_accept: false, has_script: false, force_server_packs : false,behavior: vec![], texture: vec![] };
-                self.send(resource_info).unwrap();
+            (State::ResourcePacks, ResourcePackChunkRequest::ID) => {
+                self.handle_chunk_request(payload);
             }
-            _ => {
-                println!("unknown packet ID {}",&payload[0]);
+            (State::ResourcePacks | State::StartGame, ClientCacheStatus::ID) => {}
+            (State::StartGame | State::Spawned, RequestChunkRadius::ID) => {
+                self.handle_chunk_radius(payload);
+            }
+            (State::StartGame, SetLocalPlayerAsInitialized::ID) => {
+                self.handle_initialized(payload);
+            }
+            (State::StartGame | State::Spawned, _) => {
+                let _ = self
+                    .events
+                    .send(Event::Packet(self.session_id(0), payload.to_vec()));
+            }
+            (state, id) => {
+                eprintln!(
+                    "unexpected packet ID {} in state {:?} from {}",
+                    id, state, self.address
+                );
+                self.kick(
+                    DisconnectReason::UnexpectedPacket,
+                    "disconnectionScreen.unexpectedPacket",
+                );
             }
         }
     }
 
-    pub fn handle_login(&mut self, payload: &[u8]) {
-        let login = match decode::<LoginPacket>(payload) {
+    /// Packets from split-screen players. They log in once the primary player is in the game
+    /// and are forwarded as their own sessions from then on.
+    fn handle_sub_client(&mut self, header: Header, payload: &[u8]) {
+        if !matches!(self.state, State::StartGame | State::Spawned) {
+            eprintln!(
+                "sub-client packet ID {} in state {:?} from {}",
+                header.id, self.state, self.address
+            );
+            self.kick(
+                DisconnectReason::UnexpectedPacket,
+                "disconnectionScreen.unexpectedPacket",
+            );
+            return;
+        }
+        if self.sub_clients.contains(&header.sender) {
+            let _ = self.events.send(Event::Packet(
+                self.session_id(header.sender),
+                payload.to_vec(),
+            ));
+        } else if header.id == SubClientLogin::ID {
+            self.handle_sub_client_login(header.sender, payload);
+        } else {
+            eprintln!(
+                "packet ID {} from sub-client {} of {} before its login",
+                header.id, header.sender, self.address
+            );
+        }
+    }
+
+    fn handle_sub_client_login(&mut self, sub_client: u8, payload: &[u8]) {
+        let login = match decode::<SubClientLogin>(payload, self.codec.protocol) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("invalid sub-client login {}", e);
+                self.kick_sub_client(
+                    sub_client,
+                    DisconnectReason::LoginPacketNoCert,
+                    "disconnectionScreen.notAuthenticated",
+                );
+                return;
+            }
+        };
+        let (pubkey, extra_data) = match self.config.login.verify(login.chain) {
             Ok(p) => p,
             Err(e) => {
-                dbg!(e);
-                self.disconnected();
+                eprintln!("verify sub-client jwt error {}", e);
+                self.kick_sub_client(
+                    sub_client,
+                    DisconnectReason::NotAuthenticated,
+                    "disconnectionScreen.notAuthenticated",
+                );
                 return;
             }
         };
+        let player_data = match verify_skin(login.player_data, &pubkey) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("verify sub-client player data error {}", e);
+                self.kick_sub_client(
+                    sub_client,
+                    DisconnectReason::InvalidPlatformSkin,
+                    "disconnectionScreen.invalidSkin",
+                );
+                return;
+            }
+        };
+        if !self.slots.take() {
+            let _ = self.send_to(sub_client, PlayStatus::FailedServerFull);
+            self.kick_sub_client(
+                sub_client,
+                DisconnectReason::ServerFull,
+                "disconnectionScreen.serverFull",
+            );
+            return;
+        }
+        let _ = self.outbound.send(Outbound::PlayerCount);
+        self.sub_clients.push(sub_client);
 
-        //dbg!(&login.chain);
+        if let Err(e) = self.send_to(sub_client, PlayStatus::LoginSuccess) {
+            eprintln!("error while encoding play status {}", e);
+        }
+        let session = Session::new(
+            self.session_id(sub_client),
+            self.codec.protocol,
+            extra_data,
+            player_data,
+            self.commands.clone(),
+        );
+        let _ = self.events.send(Event::Connected(session));
+    }
 
-        if login.protocol_version != PROTOCOL_VERSION {
-            let play_satus = PlayStatus::FailedClient;
-            self.send(play_satus).unwrap();
+    fn session_id(&self, sub_client: u8) -> SessionId {
+        SessionId {
+            address: self.address,
+            sub_client,
         }
+    }
 
-        let verify = match verify::verify(login.chain) {
+    fn handle_pack_response(&mut self, payload: &[u8]) {
+        let response = match decode::<ResourcePackClientResponse>(payload, self.codec.protocol) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("invalid resource pack response {}", e);
+                self.kick(
+                    DisconnectReason::UnexpectedPacket,
+                    "disconnectionScreen.unexpectedPacket",
+                );
+                return;
+            }
+        };
+        match response.status {
+            ResponseStatus::SendPacks => {
+                let config = self.config.clone();
+                for id in &response.pack_ids {
+                    let pack = match find_pack(&config.resource_packs, id) {
+                        Some(p) => p,
+                        None => {
+                            eprintln!("unknown resource pack {} from {}", id, self.address);
+                            self.kick(
+                                DisconnectReason::ResourcePackProblem,
+                                "disconnectionScreen.resourcePack",
+                            );
+                            return;
+                        }
+                    };
+                    // clients download these themselves
+                    if pack.url().is_some() {
+                        eprintln!("chunks of URL pack {} asked by {}", id, self.address);
+                        self.kick(
+                            DisconnectReason::ResourcePackProblem,
+                            "disconnectionScreen.resourcePack",
+                        );
+                        return;
+                    }
+                    let info = ResourcePackDataInfo {
+                        pack_id: pack.id(),
+                        max_chunk_size: CHUNK_SIZE,
+                        chunk_count: pack.chunk_count(),
+                        size: pack.size(),
+                        hash: pack.hash().to_vec(),
+                        premium: false,
+                        pack_type: pack.pack_type,
+                    };
+                    if !self.send_or_kick(info) {
+                        return;
+                    }
+                }
+            }
+            ResponseStatus::HaveAllPacks => {
+                let stack = self.pack_stack();
+                self.send_or_kick(stack);
+            }
+            ResponseStatus::Completed => {
+                self.start_game();
+            }
+            ResponseStatus::Refused if self.config.require_packs => {
+                self.kick(
+                    DisconnectReason::ResourcePackProblem,
+                    "disconnectionScreen.resourcePack",
+                );
+            }
+            // the client joins without the packs it refused
+            ResponseStatus::Refused => {
+                let stack = self.pack_stack();
+                self.send_or_kick(stack);
+            }
+            ResponseStatus::None => {}
+        }
+    }
+
+    fn handle_chunk_request(&mut self, payload: &[u8]) {
+        let request = match decode::<ResourcePackChunkRequest>(payload, self.codec.protocol) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("invalid resource pack chunk request {}", e);
+                self.kick(
+                    DisconnectReason::UnexpectedPacket,
+                    "disconnectionScreen.unexpectedPacket",
+                );
+                return;
+            }
+        };
+        let config = self.config.clone();
+        let pack = find_pack(&config.resource_packs, &request.pack_id);
+        if pack.is_some_and(|p| p.url().is_some()) {
+            eprintln!(
+                "chunk of URL pack {} asked by {}",
+                request.pack_id, self.address
+            );
+            self.kick(
+                DisconnectReason::ResourcePackProblem,
+                "disconnectionScreen.resourcePack",
+            );
+            return;
+        }
+        if let Some(pack) = pack {
+            // a tiny request for a whole chunk, asking twice is abuse
+            let sent = self.sent_chunks.entry(pack.id()).or_default();
+            if sent.len() >= pack.chunk_count() as usize || !sent.insert(request.chunk_index) {
+                eprintln!(
+                    "repeated resource pack chunk {} {} from {}",
+                    request.pack_id, request.chunk_index, self.address
+                );
+                self.kick(
+                    DisconnectReason::UnexpectedPacket,
+                    "disconnectionScreen.unexpectedPacket",
+                );
+                return;
+            }
+        }
+        match pack.and_then(|p| p.chunk(request.chunk_index)) {
+            Some(data) => {
+                let chunk = ResourcePackChunkData {
+                    pack_id: request.pack_id,
+                    chunk_index: request.chunk_index,
+                    offset: request.chunk_index as u64 * CHUNK_SIZE as u64,
+                    data: data.to_vec(),
+                };
+                self.send_or_kick(chunk);
+            }
+            None => {
+                eprintln!(
+                    "unknown resource pack chunk {} {} from {}",
+                    request.pack_id, request.chunk_index, self.address
+                );
+                self.kick(
+                    DisconnectReason::ResourcePackProblem,
+                    "disconnectionScreen.resourcePack",
+                );
+            }
+        }
+    }
+
+    /// Sends `StartGame` and the data the client needs before it can ask for chunks.
+    fn start_game(&mut self) {
+        let spawn = self.config.spawn.clone();
+        self.entity_id = NEXT_ENTITY_ID.fetch_add(1, Ordering::SeqCst);
+        let start_game = StartGame {
+            entity_id: self.entity_id as i64,
+            runtime_id: self.entity_id,
+            engine: version::game_version(self.codec.protocol)
+                .unwrap_or_default()
+                .to_owned(),
+            ..spawn.start_game
+        };
+        // the spawn data comes from the config, NBT in it may not encode
+        let sent = self.send_or_kick(start_game)
+            && self.send_or_kick(BiomeDefinitionList {
+                definitions: spawn.biome_definitions,
+            })
+            && self.send_or_kick(AvailableActorIdentifiers {
+                identifiers: spawn.actor_identifiers,
+            })
+            && self.send_or_kick(CreativeContent {
+                items: spawn.creative_items,
+            });
+        if sent {
+            self.state = State::StartGame;
+        }
+    }
+
+    /// Grants a view distance. The first one also publishes the chunks around the spawn and
+    /// lets the player spawn. The request is passed on so the game knows the distance.
+    fn handle_chunk_radius(&mut self, payload: &[u8]) {
+        let request = match decode::<RequestChunkRadius>(payload, self.codec.protocol) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("invalid chunk radius request {} from {}", e, self.address);
+                self.bad_packet();
+                return;
+            }
+        };
+        let spawn = &self.config.spawn;
+        let mut radius = request.chunk_radius.min(spawn.max_chunk_radius);
+        if request.max_chunk_radius > 0 {
+            radius = radius.min(request.max_chunk_radius as i32);
+        }
+        let radius = radius.max(1);
+        let position = spawn.start_game.position;
+        let first = self.chunk_radius.is_none();
+        self.chunk_radius = Some(radius);
+
+        if !self.send_or_kick(ChunkRadiusUpdated {
+            chunk_radius: radius,
+        }) {
+            return;
+        }
+        if first {
+            let publisher = NetworkChunkPublisherUpdate {
+                position: BlockPos {
+                    x: position.x.floor() as i32,
+                    y: position.y.floor() as i32,
+                    z: position.z.floor() as i32,
+                },
+                radius: radius as u32 * 16,
+                saved_chunks: vec![],
+            };
+            if !(self.send_or_kick(publisher) && self.send_or_kick(PlayStatus::PlayerSpawn)) {
+                return;
+            }
+        }
+        let _ = self
+            .events
+            .send(Event::Packet(self.session_id(0), payload.to_vec()));
+    }
+
+    fn handle_initialized(&mut self, payload: &[u8]) {
+        match decode::<SetLocalPlayerAsInitialized>(payload, self.codec.protocol) {
+            Ok(p) if p.runtime_id == self.entity_id => {}
+            Ok(p) => {
+                eprintln!(
+                    "initialized with runtime ID {} instead of {} from {}",
+                    p.runtime_id, self.entity_id, self.address
+                );
+                self.bad_packet();
+                return;
+            }
+            Err(e) => {
+                eprintln!("invalid initialized packet {} from {}", e, self.address);
+                self.bad_packet();
+                return;
+            }
+        }
+        self.state = State::Spawned;
+        let _ = self
+            .events
+            .send(Event::Packet(self.session_id(0), payload.to_vec()));
+    }
+
+    fn packs_info(&self) -> ResourcePacksInfo {
+        let packs = &self.config.resource_packs;
+        let of_type = |pack_type| {
+            packs
+                .iter()
+                .filter(|p| p.pack_type == pack_type)
+                .map(ResourcePack::info)
+                .collect()
+        };
+        ResourcePacksInfo {
+            force_accept: self.config.require_packs,
+            has_script: packs.iter().any(|p| p.has_scripts),
+            force_server_packs: false,
+            behavior: of_type(PackType::Behavior),
+            texture: of_type(PackType::Resources),
+            urls: packs.iter().filter_map(ResourcePack::pack_url).collect(),
+        }
+    }
+
+    fn pack_stack(&self) -> ResourcePackStack {
+        let packs = &self.config.resource_packs;
+        let of_type = |pack_type| {
+            packs
+                .iter()
+                .filter(|p| p.pack_type == pack_type)
+                .map(ResourcePack::stack)
+                .collect()
+        };
+        ResourcePackStack {
+            must_accept: self.config.require_packs,
+            behavior: of_type(PackType::Behavior),
+            texture: of_type(PackType::Resources),
+            game_version: "*".to_owned(),
+            experiments: vec![],
+            experiments_previously_toggled: false,
+        }
+    }
+
+    fn handle_network_settings(&mut self, payload: &[u8]) {
+        let request = match decode::<RequestNetworkSettings>(payload, self.codec.protocol) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("invalid network settings request {}", e);
+                self.kick(
+                    DisconnectReason::UnexpectedPacket,
+                    "disconnectionScreen.unexpectedPacket",
+                );
+                return;
+            }
+        };
+        if !self.check_version(request.protocol_version) {
+            return;
+        }
+        let settings = NetworkSettings {
+            compression_threshold: self.config.compression_threshold,
+            compression: self.config.compression,
+            client_throttle: false,
+            client_throttle_threshold: 0,
+            client_throttle_scalar: 0.0,
+        };
+        if !self.send_or_kick(settings) {
+            return;
+        }
+        // the answer itself goes out uncompressed
+        self.flush();
+        self.codec.compression = Some(self.config.compression);
+        self.state = State::Login;
+    }
+
+    /// Adopts the client's protocol, or rejects it with the matching `PlayStatus`.
+    fn check_version(&mut self, protocol: u32) -> bool {
+        self.codec.protocol = version::closest(protocol);
+        match version::check(protocol) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("unsupported protocol {} from {}", protocol, self.address);
+                match e {
+                    VersionMismatch::OutdatedClient => {
+                        if self.send_or_kick(PlayStatus::FailedClient) {
+                            self.kick(
+                                DisconnectReason::OutdatedClient,
+                                "disconnectionScreen.outdatedClient",
+                            );
+                        }
+                    }
+                    VersionMismatch::OutdatedServer => {
+                        if self.send_or_kick(PlayStatus::FailedServer) {
+                            self.kick(
+                                DisconnectReason::OutdatedServer,
+                                "disconnectionScreen.outdatedServer",
+                            );
+                        }
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    pub fn handle_login(&mut self, payload: &[u8]) {
+        let login = match decode::<LoginPacket>(payload, self.codec.protocol) {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("invalid login {}", e);
+                self.kick(
+                    DisconnectReason::LoginPacketNoCert,
+                    "disconnectionScreen.notAuthenticated",
+                );
+                return;
+            }
+        };
+
+        if !self.check_version(login.protocol_version) {
+            return;
+        }
+
+        let verify = match self.config.login.verify(login.chain) {
             Ok(p) => p,
             Err(e) => {
                 eprintln!("verify jwt error {}", e);
-                self.disconnected();
+                self.kick(
+                    DisconnectReason::NotAuthenticated,
+                    "disconnectionScreen.notAuthenticated",
+                );
                 return;
             }
         };
         let pubkey = verify.0;
         let extra_data = verify.1;
 
+        if !self.slots.take() {
+            if self.send_or_kick(PlayStatus::FailedServerFull) {
+                self.kick(
+                    DisconnectReason::ServerFull,
+                    "disconnectionScreen.serverFull",
+                );
+            }
+            return;
+        }
+        self.has_slot = true;
+
         println!("connected {} {}", extra_data.display_name, extra_data.xuid);
+        let _ = self.outbound.send(Outbound::PlayerCount);
 
-        let _player_data = match verify_skin(login.player_data, &pubkey) {
+        let player_data = match verify_skin(login.player_data, &pubkey) {
             Ok(p) => p,
             Err(e) => {
                 eprintln!("verify player data error {}", e);
-                self.disconnected();
+                self.kick(
+                    DisconnectReason::InvalidPlatformSkin,
+                    "disconnectionScreen.invalidSkin",
+                );
                 return;
             }
         };
@@ -179,7 +954,10 @@ impl Connection {
             Ok(p) => p,
             Err(e) => {
                 eprintln!("key exchange error {}", e);
-                self.disconnected();
+                self.kick(
+                    DisconnectReason::UnrecoverableError,
+                    "disconnectionScreen.internalError",
+                );
                 return;
             }
         };
@@ -189,48 +967,602 @@ impl Connection {
             Err(e) => eprintln!("error while encoding server2client {}", e),
         }
 
-        self.cipher = Some(cipher);
+        self.codec.set_cipher(cipher);
+        self.login = Some((extra_data, player_data));
+        self.state = State::Handshake;
     }
 
     pub fn send<T: Packet>(&mut self, packet: T) -> std::io::Result<()> {
-        let mut buff = frame(packet)?;
-        self.send_queue.append(&mut buff);
-        Ok(())
+        self.send_to(0, packet)
     }
 
-    pub async fn update(&mut self) {
-        if !self.send_queue.is_empty() {
-            let mut compressor =
-                flate2::write::DeflateEncoder::new(vec![], flate2::Compression::new(7));
-            compressor.write_all(&self.send_queue).unwrap();
-            let mut compressed = compressor.finish().unwrap();
+    /// Sends a packet addressed to one sub-client.
+    pub fn send_to<T: Packet>(&mut self, sub_client: u8, packet: T) -> std::io::Result<()> {
+        let buff = encode_for::<T>(packet, self.codec.protocol, 0, sub_client)?;
+        self.send_raw(&buff);
+        Ok(())
+    }
 
-            if self.encryption {
-                self.cipher
-                    .as_mut()
-                    .unwrap()
-                    .encode(&mut compressed)
-                    .unwrap(); //とりあえずunwrap
+    /// Sends a packet, kicking the client if it can't be encoded. Returns whether it was sent.
+    fn send_or_kick<T: Packet>(&mut self, packet: T) -> bool {
+        match self.send(packet) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!(
+                    "error while encoding packet ID {} for {} {}",
+                    T::ID,
+                    self.address,
+                    e
+                );
+                self.kick(
+                    DisconnectReason::UnrecoverableError,
+                    "disconnectionScreen.internalError",
+                );
+                false
             }
+        }
+    }
 
-            compressed.insert(0, 0xFE); //MCPE Packet
-            self.socket
-                .lock()
-                .await
-                .send_to(&self.address, &compressed)
-                .await
-                .unwrap();
-            self.send_queue.clear();
+    pub fn send_raw(&mut self, payload: &[u8]) {
+        if let Err(e) = self.codec.queue(payload) {
+            eprintln!("error while framing packet {}", e);
+        }
+    }
+
+    /// Hands everything queued to the socket task as one batch, then asks it to close the
+    /// RakNet session if the connection was kicked. The final `Disconnect` therefore always
+    /// goes out through the cipher before the session ends.
+    pub fn flush(&mut self) {
+        match self.codec.flush() {
+            Ok(Some(batch)) => {
+                let _ = self.outbound.send(Outbound::Send(self.address, batch));
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("error while encoding batch {} for {}", e, self.address),
+        }
+        if self.close_session {
+            self.close_session = false;
+            let _ = self.outbound.send(Outbound::Close(self.address));
         }
     }
     pub fn bad_packet(&mut self) {
-        todo!()
+        self.kick(
+            DisconnectReason::UnrecoverableError,
+            "disconnectionScreen.badPacket",
+        );
+    }
+    /// Sends `Disconnect` and closes the connection. The RakNet session is closed on the next `flush`.
+    pub fn kick(&mut self, reason: DisconnectReason, message: &str) {
+        if self.closed {
+            return;
+        }
+        let disconnect = Disconnect {
+            reason,
+            hide_kick_message: false,
+            kick_message: message.to_owned(),
+        };
+
+        if let Err(e) = self.send(disconnect) {
+            eprintln!("error while encoding disconnect {}", e);
+        }
+        self.close_session = true;
+        self.disconnected(reason, message);
+    }
+    /// Sends `Disconnect` to a split-screen player. The connection and the other players on it
+    /// stay up.
+    pub fn kick_sub_client(&mut self, sub_client: u8, reason: DisconnectReason, message: &str) {
+        if self.closed {
+            return;
+        }
+        let disconnect = Disconnect {
+            reason,
+            hide_kick_message: false,
+            kick_message: message.to_owned(),
+        };
+        if let Err(e) = self.send_to(sub_client, disconnect) {
+            eprintln!("error while encoding disconnect {}", e);
+        }
+        if let Some(i) = self.sub_clients.iter().position(|p| *p == sub_client) {
+            self.sub_clients.remove(i);
+            self.slots.release();
+            let _ = self.outbound.send(Outbound::PlayerCount);
+            let _ = self.events.send(Event::Disconnected(
+                self.session_id(sub_client),
+                reason,
+                message.to_owned(),
+            ));
+        }
+    }
+
+    /// Cleans up after the connection ended, whichever side ended it. Authenticated sessions
+    /// get exactly one `Event::Disconnected`.
+    pub fn disconnected(&mut self, reason: DisconnectReason, message: &str) {
+        if self.closed {
+            return;
+        }
+        self.closed = true;
+        self.login = None;
+        // split-screen players only exist next to a primary player holding a slot, so the
+        // player count update below covers theirs too
+        for sub_client in std::mem::take(&mut self.sub_clients) {
+            self.slots.release();
+            let _ = self.events.send(Event::Disconnected(
+                self.session_id(sub_client),
+                reason,
+                message.to_owned(),
+            ));
+        }
+        if self.has_slot {
+            self.has_slot = false;
+            self.slots.release();
+            let _ = self.outbound.send(Outbound::PlayerCount);
+        }
+        if self.connected {
+            self.connected = false;
+            let _ = self.events.send(Event::Disconnected(
+                self.session_id(0),
+                reason,
+                message.to_owned(),
+            ));
+        }
+    }
+}
+
+// a connection in `state` whose outbound batches and events can be read back
+#[cfg(test)]
+fn test_connection(
+    state: State,
+    config: Config,
+) -> (
+    Connection,
+    UnboundedReceiver<Event>,
+    UnboundedReceiver<Outbound>,
+) {
+    let (events, event_receiver) = mpsc::unbounded_channel();
+    let (outbound, outbound_receiver) = mpsc::unbounded_channel();
+    let mut connection = Connection::new(
+        "127.0.0.1:19132".parse().unwrap(),
+        events,
+        outbound,
+        Arc::new(Slots::new(10)),
+        Arc::new(config),
+        Arc::new(Limiter::new(crate::rate_limit::RateLimits::default())),
+    );
+    connection.state = state;
+    connection.codec.compression = Some(Compression::Zlib);
+    (connection, event_receiver, outbound_receiver)
+}
+
+// what the socket task got, with batches split into packets
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+enum Sent {
+    Packets(Vec<Vec<u8>>),
+    Close,
+    PlayerCount,
+}
+
+#[cfg(test)]
+fn sent(outbound: &mut UnboundedReceiver<Outbound>) -> Vec<Sent> {
+    sent_at(outbound, version::LATEST)
+}
+
+#[cfg(test)]
+fn sent_at(outbound: &mut UnboundedReceiver<Outbound>, protocol: u32) -> Vec<Sent> {
+    let mut client = Codec::new(protocol);
+    client.compression = Some(Compression::Zlib);
+    let mut sent = vec![];
+    while let Ok(message) = outbound.try_recv() {
+        sent.push(match message {
+            Outbound::Send(_, batch) => Sent::Packets(client.decode(batch).unwrap()),
+            Outbound::Close(_) => Sent::Close,
+            Outbound::PlayerCount => Sent::PlayerCount,
+        });
+    }
+    sent
+}
+
+#[cfg(test)]
+fn packet<T: Packet>(packet: T) -> Vec<u8> {
+    encode_for(packet, version::LATEST, 0, 0).unwrap()
+}
+
+// the `Disconnect` a kick sends, then the session closing
+#[cfg(test)]
+fn kicked(reason: DisconnectReason, message: &str) -> Vec<Sent> {
+    vec![
+        Sent::Packets(vec![packet(Disconnect {
+            reason,
+            hide_kick_message: false,
+            kick_message: message.to_owned(),
+        })]),
+        Sent::Close,
+    ]
+}
+
+#[test]
+fn repeated_pack_chunks() {
+    let pack = ResourcePack::from_bytes(crate::resource_pack::test_pack(
+        "",
+        r#"{"type":"resources"}"#,
+        CHUNK_SIZE as usize + 1,
+    ))
+    .unwrap();
+    let pack_id = pack.id();
+    let config = Config {
+        resource_packs: vec![pack],
+        ..Config::default()
+    };
+    let (mut connection, _, mut outbound) = test_connection(State::ResourcePacks, config);
+    let request = |chunk_index| {
+        packet(ResourcePackChunkRequest {
+            pack_id: pack_id.clone(),
+            chunk_index,
+        })
+    };
+
+    connection.handle_packet(&request(1));
+    connection.flush();
+    match &sent(&mut outbound)[..] {
+        [Sent::Packets(packets)] => {
+            let chunk = decode::<ResourcePackChunkData>(&packets[0], version::LATEST).unwrap();
+            assert_eq!(chunk.chunk_index, 1);
+            assert_eq!(chunk.offset, CHUNK_SIZE as u64);
+        }
+        sent => panic!("{:?}", sent),
+    }
+    connection.handle_packet(&request(1));
+    connection.flush();
+    assert_eq!(
+        sent(&mut outbound),
+        kicked(
+            DisconnectReason::UnexpectedPacket,
+            "disconnectionScreen.unexpectedPacket"
+        )
+    );
+
+    // two chunks, a third request is one too many whatever its index
+    let config = connection.config.clone();
+    let (mut connection, _, mut outbound) =
+        test_connection(State::ResourcePacks, (*config).clone());
+    for chunk_index in [0, 1, 2] {
+        connection.handle_packet(&request(chunk_index));
     }
-    /*pub fn disconnect(&mut self) {
-        let disconnect = Disconnect{hide_kick_message:false, kick_message: "something went wrong :(".to_owned() };
+    connection.flush();
+    match &sent(&mut outbound)[..] {
+        [Sent::Packets(packets), Sent::Close] => {
+            assert_eq!(packets.len(), 3);
+            let disconnect = decode::<Disconnect>(&packets[2], version::LATEST).unwrap();
+            assert_eq!(disconnect.reason, DisconnectReason::UnexpectedPacket);
+        }
+        sent => panic!("{:?}", sent),
+    }
+}
+
+#[test]
+fn socket_rate_limits() {
+    let (mut connection, _, mut outbound) =
+        test_connection(State::ResourcePacks, Config::default());
+    // a throttled batch waits undecoded, garbage or not
+    let ready = Instant::now() + std::time::Duration::from_secs(1);
+    connection.inbound(Inbound::Packet(vec![0xff; 16], Some(ready)));
+    assert!(matches!(connection.held, Some((p, Held::Batch(_))) if p == ready));
+    connection.flush();
+    assert!(sent(&mut outbound).is_empty());
+
+    connection.inbound(Inbound::OverLimit(Kind::Bytes));
+    connection.flush();
+    assert_eq!(
+        sent(&mut outbound),
+        kicked(DisconnectReason::Kicked, "Sending too fast")
+    );
+}
+
+#[tokio::test]
+async fn run_ends() {
+    // shutdown doesn't wait for a held batch
+    let (mut connection, _, mut outbound) =
+        test_connection(State::ResourcePacks, Config::default());
+    let far = Instant::now() + std::time::Duration::from_secs(3600);
+    connection.held = Some((far, Held::Batch(vec![])));
+    let (_sender, inbound) = mpsc::channel(INBOUND_QUEUE);
+    let (closing, shutdown) = watch::channel(None);
+    let task = tokio::spawn(connection.run(inbound, shutdown));
+    closing.send(Some("Server closed".to_owned())).unwrap();
+    task.await.unwrap();
+    assert_eq!(
+        sent(&mut outbound),
+        kicked(DisconnectReason::Shutdown, "Server closed")
+    );
+
+    // the socket task dropping its sender ends the connection, commands or not
+    let (mut connection, mut events, _) = test_connection(State::Spawned, Config::default());
+    connection.connected = true;
+    let _commands = connection.commands.clone();
+    let (sender, inbound) = mpsc::channel(INBOUND_QUEUE);
+    let (_closing, shutdown) = watch::channel(None);
+    let task = tokio::spawn(connection.run(inbound, shutdown));
+    drop(sender);
+    task.await.unwrap();
+    assert!(matches!(
+        events.try_recv(),
+        Ok(Event::Disconnected(_, DisconnectReason::ConnectionLost, _))
+    ));
+}
 
-        self.send(disconnect).unwrap();
-    }*/
-    pub fn disconnected(&mut self) {
+#[test]
+fn unexpected_packets() {
+    let unexpected = kicked(
+        DisconnectReason::UnexpectedPacket,
+        "disconnectionScreen.unexpectedPacket",
+    );
+
+    // the handshake before any login
+    let (mut connection, _, mut outbound) = test_connection(State::Unconnected, Config::default());
+    connection.handle_packet(&packet(Client2ServerHandshake {}));
+    connection.flush();
+    assert_eq!(sent(&mut outbound), unexpected);
+
+    // a pack response before the handshake
+    let (mut connection, _, mut outbound) = test_connection(State::Login, Config::default());
+    connection.handle_packet(&packet(ResourcePackClientResponse {
+        status: ResponseStatus::Completed,
+        pack_ids: vec![],
+    }));
+    connection.flush();
+    assert_eq!(sent(&mut outbound), unexpected);
+    assert_eq!(connection.state, State::Login);
+}
+
+#[test]
+fn kick_on_the_wire() {
+    // the reason is only sent from 1.20.40, as a zigzag VarI32, and the batch goes out before
+    // the session closes
+    let zigzag = DisconnectReason::Kicked as u8 * 2;
+    for (protocol, reason) in [
+        (version::V1_20_30, vec![]),
+        (version::V1_20_40, vec![zigzag]),
+        (version::LATEST, vec![zigzag]),
+    ] {
+        let (mut connection, _, mut outbound) = test_connection(State::Spawned, Config::default());
+        connection.codec.protocol = protocol;
+        connection.kick(DisconnectReason::Kicked, "bye");
+        connection.flush();
+        let mut disconnect = vec![Disconnect::ID as u8];
+        disconnect.extend(reason);
+        disconnect.extend([0, 3, b'b', b'y', b'e']);
+        assert_eq!(
+            sent_at(&mut outbound, protocol),
+            [Sent::Packets(vec![disconnect]), Sent::Close]
+        );
+    }
+}
+
+#[tokio::test]
+async fn disconnected_once() {
+    let disconnects = |events: &mut UnboundedReceiver<Event>| {
+        let mut count = 0;
+        while let Ok(event) = events.try_recv() {
+            assert!(matches!(event, Event::Disconnected(..)));
+            count += 1;
+        }
+        count
+    };
+
+    // a kick, then RakNet reporting the session gone
+    let (mut connection, mut events, mut outbound) =
+        test_connection(State::Spawned, Config::default());
+    connection.connected = true;
+    connection.kick(DisconnectReason::Kicked, "bye");
+    connection.disconnected(DisconnectReason::Disconnected, "connection closed");
+    connection.flush();
+    assert_eq!(disconnects(&mut events), 1);
+    assert_eq!(sent(&mut outbound), kicked(DisconnectReason::Kicked, "bye"));
+
+    // the other way around, nothing is left to send to
+    let (mut connection, mut events, mut outbound) =
+        test_connection(State::Spawned, Config::default());
+    connection.connected = true;
+    connection.disconnected(DisconnectReason::Disconnected, "connection closed");
+    connection.kick(DisconnectReason::Kicked, "bye");
+    connection.flush();
+    assert_eq!(disconnects(&mut events), 1);
+    assert!(sent(&mut outbound).is_empty());
+
+    // both queued for a running connection
+    let (mut connection, mut events, _) = test_connection(State::Spawned, Config::default());
+    connection.connected = true;
+    let commands = connection.commands.clone();
+    let (sender, inbound) = mpsc::channel(INBOUND_QUEUE);
+    let (_closing, shutdown) = watch::channel(None);
+    commands
+        .send(Command::Kick(0, DisconnectReason::Kicked, "bye".to_owned()))
+        .unwrap();
+    sender
+        .try_send(Inbound::Disconnected(
+            DisconnectReason::Disconnected,
+            "connection closed".to_owned(),
+        ))
+        .unwrap();
+    connection.run(inbound, shutdown).await;
+    assert_eq!(disconnects(&mut events), 1);
+}
+
+#[test]
+fn slots() {
+    let slots = Arc::new(Slots::new(4));
+    for _ in 0..4 {
+        assert!(slots.take());
+    }
+    assert!(!slots.take());
+    slots.release();
+
+    // players joining and leaving at the limit never push it over
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let slots = slots.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10_000 {
+                    if slots.take() {
+                        assert!(slots.online() <= 4);
+                        slots.release();
+                    }
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(slots.online(), 3);
+}
+
+#[tokio::test]
+async fn slots_released() {
+    // a player with two split-screen players on the connection
+    let joined = || {
+        let (mut connection, events, _) = test_connection(State::Spawned, Config::default());
+        connection.connected = true;
+        for _ in 0..3 {
+            assert!(connection.slots.take());
+        }
+        connection.has_slot = true;
+        connection.sub_clients = vec![1, 2];
+        (connection, events)
+    };
+
+    let (mut connection, _) = joined();
+    connection.kick_sub_client(1, DisconnectReason::Kicked, "bye");
+    assert_eq!(connection.slots.online(), 2);
+    connection.kick(DisconnectReason::Kicked, "bye");
+    assert_eq!(connection.slots.online(), 0);
+
+    let (mut connection, _) = joined();
+    connection.disconnected(DisconnectReason::Disconnected, "connection closed");
+    assert_eq!(connection.slots.online(), 0);
+
+    let (connection, _) = joined();
+    let slots = connection.slots.clone();
+    let (sender, inbound) = mpsc::channel(INBOUND_QUEUE);
+    let (_closing, shutdown) = watch::channel(None);
+    drop(sender);
+    connection.run(inbound, shutdown).await;
+    assert_eq!(slots.online(), 0);
+
+    let (connection, _) = joined();
+    let slots = connection.slots.clone();
+    let (_sender, inbound) = mpsc::channel(INBOUND_QUEUE);
+    let (closing, shutdown) = watch::channel(None);
+    closing.send(Some("Server closed".to_owned())).unwrap();
+    connection.run(inbound, shutdown).await;
+    assert_eq!(slots.online(), 0);
+}
+
+#[test]
+fn spawn_sequence() {
+    let ids = |sent: Vec<Sent>| match &sent[..] {
+        [Sent::Packets(packets)] => (
+            packets
+                .iter()
+                .map(|p| Header::read(p).unwrap().0.id)
+                .collect::<Vec<_>>(),
+            packets.clone(),
+        ),
+        sent => panic!("{:?}", sent),
+    };
+    let (mut connection, mut events, mut outbound) =
+        test_connection(State::ResourcePacks, Config::default());
+
+    connection.handle_packet(&packet(ResourcePackClientResponse {
+        status: ResponseStatus::Completed,
+        pack_ids: vec![],
+    }));
+    connection.flush();
+    let (sent_ids, packets) = ids(sent(&mut outbound));
+    assert_eq!(
+        sent_ids,
+        [
+            StartGame::ID,
+            BiomeDefinitionList::ID,
+            AvailableActorIdentifiers::ID,
+            CreativeContent::ID,
+        ]
+    );
+    let start_game = decode::<StartGame>(&packets[0], version::LATEST).unwrap();
+    assert_eq!(connection.state, State::StartGame);
+
+    // the first radius lets the player spawn, later ones only answer
+    for first in [true, false] {
+        connection.handle_packet(&packet(RequestChunkRadius {
+            chunk_radius: 8,
+            max_chunk_radius: 0,
+        }));
+        connection.flush();
+        let (sent_ids, packets) = ids(sent(&mut outbound));
+        if first {
+            assert_eq!(
+                sent_ids,
+                [
+                    ChunkRadiusUpdated::ID,
+                    NetworkChunkPublisherUpdate::ID,
+                    PlayStatus::ID,
+                ]
+            );
+            assert!(matches!(
+                decode::<PlayStatus>(&packets[2], version::LATEST),
+                Ok(PlayStatus::PlayerSpawn)
+            ));
+        } else {
+            assert_eq!(sent_ids, [ChunkRadiusUpdated::ID]);
+        }
+        assert!(matches!(events.try_recv(), Ok(Event::Packet(..))));
+    }
+    assert_eq!(connection.state, State::StartGame);
+
+    connection.handle_packet(&packet(SetLocalPlayerAsInitialized {
+        runtime_id: start_game.runtime_id,
+    }));
+    connection.flush();
+    assert_eq!(connection.state, State::Spawned);
+    assert!(sent(&mut outbound).is_empty());
+    assert!(matches!(events.try_recv(), Ok(Event::Packet(..))));
+
+    // someone else's runtime ID
+    let (mut connection, _, mut outbound) = test_connection(State::StartGame, Config::default());
+    connection.entity_id = start_game.runtime_id;
+    connection.handle_packet(&packet(SetLocalPlayerAsInitialized {
+        runtime_id: start_game.runtime_id + 1,
+    }));
+    connection.flush();
+    assert_eq!(
+        sent(&mut outbound),
+        kicked(
+            DisconnectReason::UnrecoverableError,
+            "disconnectionScreen.badPacket"
+        )
+    );
+}
+
+#[test]
+fn unencodable_spawn() {
+    use crate::nbt::Tag;
+
+    // NBT lists hold one type, this can't be sent
+    let mut config = Config::default();
+    config.spawn.biome_definitions = Tag::List(vec![Tag::Byte(0), Tag::Int(0)]);
+    let (mut connection, _, mut outbound) = test_connection(State::ResourcePacks, config);
+    connection.handle_packet(&packet(ResourcePackClientResponse {
+        status: ResponseStatus::Completed,
+        pack_ids: vec![],
+    }));
+    connection.flush();
+    match &sent(&mut outbound)[..] {
+        [Sent::Packets(packets), Sent::Close] => {
+            assert_eq!(Header::read(&packets[0]).unwrap().0.id, StartGame::ID);
+            let disconnect = decode::<Disconnect>(&packets[1], version::LATEST).unwrap();
+            assert_eq!(disconnect.reason, DisconnectReason::UnrecoverableError);
+        }
+        sent => panic!("{:?}", sent),
     }
+    assert_eq!(connection.state, State::ResourcePacks);
 }
@@ -0,0 +1,324 @@
+use std::io::Result;
+
+use crate::{
+    reader::{self, Reader},
+    writer::Writer,
+};
+
+pub use bers_derive::{Decode, Encode};
+
+/// A value with a fixed wire format. Packets are written by encoding their fields in order.
+pub trait Encode {
+    fn encode(&self, cursor: &mut Writer) -> Result<()>;
+}
+
+/// The reverse of `Encode`. Decoding what `Encode` wrote gives back the same value.
+pub trait Decode: Sized {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self>;
+}
+
+/// A number in big endian. Plain numbers are little endian, like most of the protocol.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Be<T>(pub T);
+
+/// A varuint32.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VarU32(pub u32);
+
+/// A zigzag varint32.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VarI32(pub i32);
+
+/// A varuint64.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VarU64(pub u64);
+
+/// A zigzag varint64.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VarI64(pub i64);
+
+/// A string with a u32 LE length, as in the login request. Plain strings have a varuint32
+/// length. Encodes from any `AsRef<str>`, so borrowed strings need no copy.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LeString<S = String>(pub S);
+
+/// A list with a u16 LE count. Plain lists have a varuint32 count. Encodes from a `Vec` or a
+/// slice, decodes into a `Vec`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VecU16<V>(pub V);
+
+/// A list with a u32 LE count.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VecU32<V>(pub V);
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        (**self).encode(cursor)
+    }
+}
+
+macro_rules! number {
+    ($($t:ty),*) => {$(
+        impl Encode for $t {
+            fn encode(&self, cursor: &mut Writer) -> Result<()> {
+                cursor.write(&self.to_le_bytes())
+            }
+        }
+
+        impl Decode for $t {
+            fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+                let mut bytes = [0; std::mem::size_of::<$t>()];
+                cursor.read(&mut bytes)?;
+                Ok(<$t>::from_le_bytes(bytes))
+            }
+        }
+
+        impl Encode for Be<$t> {
+            fn encode(&self, cursor: &mut Writer) -> Result<()> {
+                cursor.write(&self.0.to_be_bytes())
+            }
+        }
+
+        impl Decode for Be<$t> {
+            fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+                let mut bytes = [0; std::mem::size_of::<$t>()];
+                cursor.read(&mut bytes)?;
+                Ok(Be(<$t>::from_be_bytes(bytes)))
+            }
+        }
+    )*};
+}
+
+number!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+impl Encode for bool {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_u8(*self as u8)
+    }
+}
+
+impl Decode for bool {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(cursor.read_u8()? != 0)
+    }
+}
+
+impl Encode for VarU32 {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_varu32(self.0).map(|_| ())
+    }
+}
+
+impl Decode for VarU32 {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Self(cursor.read_varu32()?))
+    }
+}
+
+impl Encode for VarI32 {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_vari32(self.0).map(|_| ())
+    }
+}
+
+impl Decode for VarI32 {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Self(cursor.read_vari32()?))
+    }
+}
+
+impl Encode for VarU64 {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_varu64(self.0).map(|_| ())
+    }
+}
+
+impl Decode for VarU64 {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Self(cursor.read_varu64()?))
+    }
+}
+
+impl Encode for VarI64 {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_vari64(self.0).map(|_| ())
+    }
+}
+
+impl Decode for VarI64 {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Self(cursor.read_vari64()?))
+    }
+}
+
+impl Encode for str {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_string(self)
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_string(self)
+    }
+}
+
+impl Decode for String {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        cursor.read_string()
+    }
+}
+
+impl<S: AsRef<str>> Encode for LeString<S> {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_le_string(self.0.as_ref())
+    }
+}
+
+impl Decode for LeString {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Self(cursor.read_le_string()?))
+    }
+}
+
+fn encode_items<T: Encode>(cursor: &mut Writer, items: &[T]) -> Result<()> {
+    for item in items {
+        item.encode(cursor)?;
+    }
+    Ok(())
+}
+
+fn decode_items<T: Decode>(cursor: &mut Reader, count: u64) -> reader::Result<Vec<T>> {
+    let count = cursor.check_length(count)?;
+    // every item takes at least a byte, so a bogus count fails before allocating much
+    let mut items = Vec::with_capacity(count.min(cursor.remaining()));
+    for _ in 0..count {
+        items.push(T::decode(cursor)?);
+    }
+    Ok(items)
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        cursor.write_varu32(self.len() as u32)?;
+        encode_items(cursor, self)
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        self.as_slice().encode(cursor)
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        let count = cursor.read_varu32()?;
+        decode_items(cursor, count.into())
+    }
+}
+
+macro_rules! prefixed {
+    ($name:ident, $count:ty) => {
+        impl<T: Encode> Encode for $name<&[T]> {
+            fn encode(&self, cursor: &mut Writer) -> Result<()> {
+                (self.0.len() as $count).encode(cursor)?;
+                encode_items(cursor, self.0)
+            }
+        }
+
+        impl<T: Encode> Encode for $name<Vec<T>> {
+            fn encode(&self, cursor: &mut Writer) -> Result<()> {
+                $name(self.0.as_slice()).encode(cursor)
+            }
+        }
+
+        impl<T: Decode> Decode for $name<Vec<T>> {
+            fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+                let count = <$count>::decode(cursor)?;
+                Ok(Self(decode_items(cursor, count.into())?))
+            }
+        }
+    };
+}
+
+prefixed!(VecU16, u16);
+prefixed!(VecU32, u32);
+
+/// A bool telling whether the value follows.
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        match self {
+            Some(p) => {
+                true.encode(cursor)?;
+                p.encode(cursor)
+            }
+            None => false.encode(cursor),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        if bool::decode(cursor)? {
+            Ok(Some(T::decode(cursor)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+fn round_trip<T: Encode + Decode + PartialEq + std::fmt::Debug>(value: T) -> Vec<u8> {
+    let mut cursor = Writer::new(vec![]);
+    value.encode(&mut cursor).unwrap();
+    let buf = cursor.get_raw_payload();
+    let mut cursor = Reader::new(&buf);
+    assert_eq!(T::decode(&mut cursor).unwrap(), value);
+    cursor.finish().unwrap();
+    buf
+}
+
+#[test]
+fn encoding() {
+    use crate::protocol::types::{
+        position::{BlockPos, Vec3},
+        uuid::Uuid,
+    };
+
+    assert_eq!(round_trip(true), vec![1]);
+    assert_eq!(round_trip(0x0102u16), vec![2, 1]);
+    assert_eq!(round_trip(Be(0x0102u16)), vec![1, 2]);
+    round_trip(-1.5f32);
+    round_trip(f64::MAX);
+    round_trip(i64::MIN);
+    assert_eq!(round_trip(VarU32(300)), vec![0xac, 0x02]);
+    assert_eq!(round_trip(VarI32(-1)), vec![0x01]);
+    round_trip(VarU64(u64::MAX));
+    round_trip(VarI64(i64::MIN));
+    assert_eq!(round_trip("ab".to_owned()), vec![2, b'a', b'b']);
+    assert_eq!(
+        round_trip(LeString("ab".to_owned())),
+        vec![2, 0, 0, 0, b'a', b'b']
+    );
+    assert_eq!(round_trip(vec![1u8, 2]), vec![2, 1, 2]);
+    assert_eq!(round_trip(VecU16(vec![1u8])), vec![1, 0, 1]);
+    assert_eq!(round_trip(VecU32(vec![1u8])), vec![1, 0, 0, 0, 1]);
+    assert_eq!(round_trip(Some(7u8)), vec![1, 7]);
+    assert_eq!(round_trip(None::<u8>), vec![0]);
+    round_trip(Uuid::parse("5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6d").unwrap());
+    round_trip(Vec3 {
+        x: 1.0,
+        y: -64.0,
+        z: 0.5,
+    });
+    round_trip(BlockPos {
+        x: -1,
+        y: 320,
+        z: i32::MAX,
+    });
+
+    // a count larger than the input fails without a huge allocation
+    let mut cursor = Reader::new(&[0xff, 0xff, 0x03]);
+    assert!(Vec::<u64>::decode(&mut cursor).is_err());
+}
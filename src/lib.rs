@@ -1,7 +1,20 @@
+// lets `bers-derive` output name `::bers` from inside this crate too
+extern crate self as bers;
+
+pub mod client;
+mod codec;
+pub mod config;
 mod connection;
+pub mod encoding;
 pub mod motd;
+pub mod nbt;
+pub mod pack_server;
 pub mod protocol;
+pub mod rate_limit;
 pub mod reader;
+pub mod resource_pack;
 pub mod server;
+pub mod session;
+pub mod world;
 pub mod writer;
 pub mod auth;
\ No newline at end of file
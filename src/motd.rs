@@ -2,6 +2,7 @@ use std::collections::VecDeque;
 
 //"MCPE;§5raknet rs;390;1.17.42;0;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;"
 //"MCPE;§b§lEU §7§l» WINTERFEST \u{e101};121;1.0;20566;100001;1821793688326766702;Hive Games;Survival"
+#[derive(Clone)]
 pub struct Motd {
     pub title: String,
     pub protocol_version: u16,
@@ -0,0 +1,154 @@
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer},
+        DeserializeOwned, IntoDeserializer, Visitor,
+    },
+    forward_to_deserialize_any,
+};
+
+use super::{Error, Tag};
+
+/// Maps a `Tag` to a serde type, the reverse of `to_tag`. Missing `Option` fields are `None`.
+pub fn from_tag<T: DeserializeOwned>(tag: Tag) -> Result<T> {
+    T::deserialize(Deserializer(tag))
+}
+
+pub struct Deserializer(Tag);
+
+type Result<T> = std::result::Result<T, Error>;
+
+impl<'de> IntoDeserializer<'de, Error> for Tag {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Deserializer {
+        Deserializer(self)
+    }
+}
+
+fn visit_seq<'de, I, T, V>(items: I, visitor: V) -> Result<V::Value>
+where
+    I: Iterator<Item = T>,
+    T: IntoDeserializer<'de, Error>,
+    V: Visitor<'de>,
+{
+    let mut seq = SeqDeserializer::new(items);
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tag::Byte(v) => visitor.visit_i8(v),
+            Tag::Short(v) => visitor.visit_i16(v),
+            Tag::Int(v) => visitor.visit_i32(v),
+            Tag::Long(v) => visitor.visit_i64(v),
+            Tag::Float(v) => visitor.visit_f32(v),
+            Tag::Double(v) => visitor.visit_f64(v),
+            Tag::ByteArray(v) => visitor.visit_byte_buf(v),
+            Tag::String(v) => visitor.visit_string(v),
+            Tag::List(v) => visit_seq(v.into_iter(), visitor),
+            Tag::Compound(v) => {
+                let mut map = MapDeserializer::new(v.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            Tag::IntArray(v) => visit_seq(v.into_iter(), visitor),
+            Tag::LongArray(v) => visit_seq(v.into_iter(), visitor),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tag::Byte(v) => visitor.visit_bool(v != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    // unsigned ints were written as the signed tag of the same width
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tag::Byte(v) => visitor.visit_u8(v as u8),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tag::Short(v) => visitor.visit_u16(v as u16),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tag::Int(v) => visitor.visit_u32(v as u32),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tag::Long(v) => visitor.visit_u64(v as u64),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    // a byte array can also fill a `Vec<u8>`
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tag::ByteArray(v) => visit_seq(v.into_iter(), visitor),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // unit variants are strings, newtype variants compounds with the variant as only key
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.0 {
+            Tag::String(v) => visitor.visit_enum(v.into_deserializer()),
+            Tag::Compound(v) if v.len() == 1 => visitor.visit_enum(MapAccessDeserializer::new(
+                MapDeserializer::new(v.into_iter()),
+            )),
+            _ => Err(Error("expected an enum".to_owned())),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u128 f32 f64 char str string bytes byte_buf tuple tuple_struct map
+        struct identifier ignored_any
+    }
+}
@@ -0,0 +1,554 @@
+//! Bedrock NBT. Packets use the network variant, with varint lengths and zigzag ints; level
+//! and pack files use the little-endian one. A `Tag` tree can be read and written directly,
+//! or mapped to and from serde types with `to_tag` and `from_tag`.
+
+mod de;
+mod ser;
+
+use std::{collections::BTreeMap, fmt, io::Result};
+
+use serde::{
+    de::{DeserializeOwned, MapAccess, SeqAccess, Visitor},
+    Deserialize, Serialize,
+};
+
+use crate::{
+    encoding::{Decode, Encode},
+    reader::{self, DecodeError, Endian, Reader},
+    writer::Writer,
+};
+
+pub use de::from_tag;
+pub use ser::to_tag;
+
+/// Deepest nesting of lists and compounds accepted, as in vanilla.
+pub const MAX_DEPTH: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// Varuint32 string lengths, zigzag varint ints and longs and list lengths.
+    Network,
+    /// Fixed-width little-endian values and u16 string lengths.
+    LittleEndian,
+}
+
+pub type Compound = BTreeMap<String, Tag>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    /// All elements have the same type.
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const END: u8 = 0;
+
+impl Tag {
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Looks up a key if this is a compound.
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(p) => p.get(key),
+            _ => None,
+        }
+    }
+
+    /// Reads a named root tag, usually a compound with an empty name.
+    pub fn read(cursor: &mut Reader, variant: Variant) -> reader::Result<(String, Tag)> {
+        let id = cursor.read_u8()?;
+        if id == END {
+            return Err(DecodeError::InvalidValue("NBT root is TAG_End".to_owned()));
+        }
+        let name = read_string(cursor, variant)?;
+        let tag = read_payload(cursor, variant, id, 0)?;
+        Ok((name, tag))
+    }
+
+    pub fn write(&self, cursor: &mut Writer, variant: Variant, name: &str) -> Result<()> {
+        cursor.write_u8(self.id())?;
+        write_string(cursor, variant, name)?;
+        write_payload(cursor, variant, self)
+    }
+}
+
+fn read_string(cursor: &mut Reader, variant: Variant) -> reader::Result<String> {
+    match variant {
+        Variant::Network => cursor.read_string(),
+        Variant::LittleEndian => {
+            let length = cursor.read_u16(Endian::Little)?;
+            let bytes = cursor.read_bytes(length.into())?;
+            match std::str::from_utf8(bytes) {
+                Ok(p) => Ok(p.to_owned()),
+                Err(e) => Err(DecodeError::InvalidUtf8(e)),
+            }
+        }
+    }
+}
+
+fn read_int(cursor: &mut Reader, variant: Variant) -> reader::Result<i32> {
+    match variant {
+        Variant::Network => cursor.read_vari32(),
+        Variant::LittleEndian => cursor.read_i32(Endian::Little),
+    }
+}
+
+fn read_long(cursor: &mut Reader, variant: Variant) -> reader::Result<i64> {
+    match variant {
+        Variant::Network => cursor.read_vari64(),
+        Variant::LittleEndian => cursor.read_i64(Endian::Little),
+    }
+}
+
+// list and array lengths are signed on the wire
+fn read_length(cursor: &mut Reader, variant: Variant) -> reader::Result<usize> {
+    let length = read_int(cursor, variant)?;
+    match u64::try_from(length) {
+        Ok(p) => cursor.check_length(p),
+        Err(_) => Err(DecodeError::InvalidValue(format!(
+            "Negative NBT length {}",
+            length
+        ))),
+    }
+}
+
+fn read_payload(
+    cursor: &mut Reader,
+    variant: Variant,
+    id: u8,
+    depth: usize,
+) -> reader::Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(DecodeError::InvalidValue("NBT nested too deep".to_owned()));
+    }
+    Ok(match id {
+        1 => Tag::Byte(cursor.read_u8()? as i8),
+        2 => Tag::Short(cursor.read_u16(Endian::Little)? as i16),
+        3 => Tag::Int(read_int(cursor, variant)?),
+        4 => Tag::Long(read_long(cursor, variant)?),
+        5 => Tag::Float(cursor.read_f32(Endian::Little)?),
+        6 => Tag::Double(cursor.read_f64(Endian::Little)?),
+        7 => {
+            let length = read_length(cursor, variant)?;
+            Tag::ByteArray(cursor.read_bytes(length)?.to_vec())
+        }
+        8 => Tag::String(read_string(cursor, variant)?),
+        9 => {
+            let element = cursor.read_u8()?;
+            let length = read_length(cursor, variant)?;
+            if element == END && length != 0 {
+                return Err(DecodeError::InvalidValue(
+                    "NBT list of TAG_End is not empty".to_owned(),
+                ));
+            }
+            let mut list = Vec::with_capacity(length.min(cursor.remaining()));
+            for _ in 0..length {
+                list.push(read_payload(cursor, variant, element, depth + 1)?);
+            }
+            Tag::List(list)
+        }
+        10 => {
+            let mut compound = Compound::new();
+            loop {
+                let id = cursor.read_u8()?;
+                if id == END {
+                    break;
+                }
+                let name = read_string(cursor, variant)?;
+                compound.insert(name, read_payload(cursor, variant, id, depth + 1)?);
+            }
+            Tag::Compound(compound)
+        }
+        11 => {
+            let length = read_length(cursor, variant)?;
+            let mut array = Vec::with_capacity(length.min(cursor.remaining()));
+            for _ in 0..length {
+                array.push(read_int(cursor, variant)?);
+            }
+            Tag::IntArray(array)
+        }
+        12 => {
+            let length = read_length(cursor, variant)?;
+            let mut array = Vec::with_capacity(length.min(cursor.remaining()));
+            for _ in 0..length {
+                array.push(read_long(cursor, variant)?);
+            }
+            Tag::LongArray(array)
+        }
+        _ => {
+            return Err(DecodeError::InvalidValue(format!(
+                "Unknown NBT tag type {}",
+                id
+            )))
+        }
+    })
+}
+
+fn write_string(cursor: &mut Writer, variant: Variant, v: &str) -> Result<()> {
+    match variant {
+        Variant::Network => cursor.write_string(v),
+        Variant::LittleEndian => {
+            let length = match u16::try_from(v.len()) {
+                Ok(p) => p,
+                Err(_) => return Err(invalid("NBT string longer than 65535 bytes")),
+            };
+            cursor.write_u16(length, Endian::Little)?;
+            cursor.write(v.as_bytes())
+        }
+    }
+}
+
+fn write_int(cursor: &mut Writer, variant: Variant, v: i32) -> Result<()> {
+    match variant {
+        Variant::Network => cursor.write_vari32(v).map(|_| ()),
+        Variant::LittleEndian => cursor.write_i32(v, Endian::Little),
+    }
+}
+
+fn write_long(cursor: &mut Writer, variant: Variant, v: i64) -> Result<()> {
+    match variant {
+        Variant::Network => cursor.write_vari64(v).map(|_| ()),
+        Variant::LittleEndian => cursor.write_i64(v, Endian::Little),
+    }
+}
+
+fn write_length(cursor: &mut Writer, variant: Variant, length: usize) -> Result<()> {
+    match i32::try_from(length) {
+        Ok(p) => write_int(cursor, variant, p),
+        Err(_) => Err(invalid("NBT list too long")),
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+fn write_payload(cursor: &mut Writer, variant: Variant, tag: &Tag) -> Result<()> {
+    match tag {
+        Tag::Byte(v) => cursor.write_u8(*v as u8),
+        Tag::Short(v) => cursor.write_u16(*v as u16, Endian::Little),
+        Tag::Int(v) => write_int(cursor, variant, *v),
+        Tag::Long(v) => write_long(cursor, variant, *v),
+        Tag::Float(v) => cursor.write_f32(*v, Endian::Little),
+        Tag::Double(v) => cursor.write_f64(*v, Endian::Little),
+        Tag::ByteArray(v) => {
+            write_length(cursor, variant, v.len())?;
+            cursor.write(v)
+        }
+        Tag::String(v) => write_string(cursor, variant, v),
+        Tag::List(list) => {
+            let element = list.first().map_or(END, Tag::id);
+            if list.iter().any(|p| p.id() != element) {
+                return Err(invalid("NBT list elements differ in type"));
+            }
+            cursor.write_u8(element)?;
+            write_length(cursor, variant, list.len())?;
+            for tag in list {
+                write_payload(cursor, variant, tag)?;
+            }
+            Ok(())
+        }
+        Tag::Compound(compound) => {
+            for (name, tag) in compound {
+                tag.write(cursor, variant, name)?;
+            }
+            cursor.write_u8(END)
+        }
+        Tag::IntArray(v) => {
+            write_length(cursor, variant, v.len())?;
+            for i in v {
+                write_int(cursor, variant, *i)?;
+            }
+            Ok(())
+        }
+        Tag::LongArray(v) => {
+            write_length(cursor, variant, v.len())?;
+            for i in v {
+                write_long(cursor, variant, *i)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Lets `Tag` fields sit in serde types. Int and long arrays go through serde as lists.
+impl Serialize for Tag {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Tag::Byte(v) => serializer.serialize_i8(*v),
+            Tag::Short(v) => serializer.serialize_i16(*v),
+            Tag::Int(v) => serializer.serialize_i32(*v),
+            Tag::Long(v) => serializer.serialize_i64(*v),
+            Tag::Float(v) => serializer.serialize_f32(*v),
+            Tag::Double(v) => serializer.serialize_f64(*v),
+            Tag::ByteArray(v) => serializer.serialize_bytes(v),
+            Tag::String(v) => serializer.serialize_str(v),
+            Tag::List(v) => v.serialize(serializer),
+            Tag::Compound(v) => v.serialize(serializer),
+            Tag::IntArray(v) => v.serialize(serializer),
+            Tag::LongArray(v) => v.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        deserializer.deserialize_any(TagVisitor)
+    }
+}
+
+struct TagVisitor;
+
+impl<'de> Visitor<'de> for TagVisitor {
+    type Value = Tag;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an NBT value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> std::result::Result<Tag, E> {
+        Ok(Tag::Byte(v as i8))
+    }
+    fn visit_i8<E>(self, v: i8) -> std::result::Result<Tag, E> {
+        Ok(Tag::Byte(v))
+    }
+    fn visit_i16<E>(self, v: i16) -> std::result::Result<Tag, E> {
+        Ok(Tag::Short(v))
+    }
+    fn visit_i32<E>(self, v: i32) -> std::result::Result<Tag, E> {
+        Ok(Tag::Int(v))
+    }
+    fn visit_i64<E>(self, v: i64) -> std::result::Result<Tag, E> {
+        Ok(Tag::Long(v))
+    }
+    fn visit_u8<E>(self, v: u8) -> std::result::Result<Tag, E> {
+        Ok(Tag::Byte(v as i8))
+    }
+    fn visit_u16<E>(self, v: u16) -> std::result::Result<Tag, E> {
+        Ok(Tag::Short(v as i16))
+    }
+    fn visit_u32<E>(self, v: u32) -> std::result::Result<Tag, E> {
+        Ok(Tag::Int(v as i32))
+    }
+    fn visit_u64<E>(self, v: u64) -> std::result::Result<Tag, E> {
+        Ok(Tag::Long(v as i64))
+    }
+    fn visit_f32<E>(self, v: f32) -> std::result::Result<Tag, E> {
+        Ok(Tag::Float(v))
+    }
+    fn visit_f64<E>(self, v: f64) -> std::result::Result<Tag, E> {
+        Ok(Tag::Double(v))
+    }
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Tag, E> {
+        Ok(Tag::String(v.to_owned()))
+    }
+    fn visit_string<E>(self, v: String) -> std::result::Result<Tag, E> {
+        Ok(Tag::String(v))
+    }
+    fn visit_bytes<E>(self, v: &[u8]) -> std::result::Result<Tag, E> {
+        Ok(Tag::ByteArray(v.to_vec()))
+    }
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> std::result::Result<Tag, E> {
+        Ok(Tag::ByteArray(v))
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Tag, A::Error> {
+        let mut list = vec![];
+        while let Some(p) = seq.next_element()? {
+            list.push(p);
+        }
+        Ok(Tag::List(list))
+    }
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Tag, A::Error> {
+        let mut compound = Compound::new();
+        while let Some((k, v)) = map.next_entry()? {
+            compound.insert(k, v);
+        }
+        Ok(Tag::Compound(compound))
+    }
+}
+
+/// Network NBT with an empty root name, as embedded in packets.
+impl Encode for Tag {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        self.write(cursor, Variant::Network, "")
+    }
+}
+
+impl Decode for Tag {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Tag::read(cursor, Variant::Network)?.1)
+    }
+}
+
+/// A serde type sent as network NBT, for packet fields.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Nbt<T>(pub T);
+
+impl<T: Serialize> Encode for Nbt<T> {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        to_tag(&self.0)?.encode(cursor)
+    }
+}
+
+impl<T: DeserializeOwned> Decode for Nbt<T> {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Nbt(from_tag(Tag::decode(cursor)?)?))
+    }
+}
+
+/// Why a value could not be mapped to or from a `Tag`.
+#[derive(Debug)]
+pub struct Error(pub String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NbtError {}", self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl serde::ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl From<Error> for DecodeError {
+    fn from(e: Error) -> Self {
+        DecodeError::InvalidValue(e.0)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+    }
+}
+
+#[test]
+fn nbt() {
+    let mut compound = Compound::new();
+    compound.insert("name".to_owned(), Tag::String("minecraft:stone".to_owned()));
+    compound.insert("int".to_owned(), Tag::Int(-2));
+    compound.insert("long".to_owned(), Tag::Long(i64::MIN));
+    compound.insert(
+        "list".to_owned(),
+        Tag::List(vec![Tag::Short(1), Tag::Short(-1)]),
+    );
+    compound.insert("empty".to_owned(), Tag::List(vec![]));
+    compound.insert("bytes".to_owned(), Tag::ByteArray(vec![1, 2]));
+    compound.insert("ints".to_owned(), Tag::IntArray(vec![1, -1]));
+    compound.insert("longs".to_owned(), Tag::LongArray(vec![i64::MAX]));
+    compound.insert("double".to_owned(), Tag::Double(0.25));
+    let tag = Tag::Compound(compound);
+
+    for variant in [Variant::Network, Variant::LittleEndian] {
+        let mut cursor = Writer::new(vec![]);
+        tag.write(&mut cursor, variant, "root").unwrap();
+        let buf = cursor.get_raw_payload();
+        let mut cursor = Reader::new(&buf);
+        assert_eq!(
+            Tag::read(&mut cursor, variant).unwrap(),
+            ("root".to_owned(), tag.clone())
+        );
+        cursor.finish().unwrap();
+        for end in 0..buf.len() {
+            assert!(Tag::read(&mut Reader::new(&buf[..end]), variant).is_err());
+        }
+    }
+
+    let mut cursor = Writer::new(vec![]);
+    Tag::Int(-2)
+        .write(&mut cursor, Variant::Network, "a")
+        .unwrap();
+    assert_eq!(cursor.get_raw_payload(), [3, 1, b'a', 3]);
+    let mut cursor = Writer::new(vec![]);
+    Tag::Int(-2)
+        .write(&mut cursor, Variant::LittleEndian, "a")
+        .unwrap();
+    assert_eq!(
+        cursor.get_raw_payload(),
+        [3, 1, 0, b'a', 0xfe, 0xff, 0xff, 0xff]
+    );
+
+    let mixed = Tag::List(vec![Tag::Byte(1), Tag::Int(1)]);
+    assert!(mixed.encode(&mut Writer::new(vec![])).is_err());
+
+    // lists nested past the limit
+    let mut deep = vec![9, 0];
+    for _ in 0..MAX_DEPTH + 10 {
+        deep.extend([9, 2]);
+    }
+    assert!(matches!(
+        Tag::read(&mut Reader::new(&deep), Variant::Network),
+        Err(DecodeError::InvalidValue(_))
+    ));
+}
+
+#[test]
+fn serde() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Block {
+        name: String,
+        states: std::collections::BTreeMap<String, Tag>,
+        version: u32,
+        runtime_id: Option<u16>,
+        hidden: bool,
+        colors: Vec<u8>,
+    }
+
+    let block = Block {
+        name: "minecraft:wool".to_owned(),
+        states: [("color".to_owned(), Tag::String("red".to_owned()))].into(),
+        version: u32::MAX,
+        runtime_id: None,
+        hidden: true,
+        colors: vec![1, 2],
+    };
+    let tag = to_tag(&block).unwrap();
+    assert_eq!(tag.get("version"), Some(&Tag::Int(-1)));
+    assert_eq!(tag.get("runtime_id"), None);
+    assert_eq!(tag.get("hidden"), Some(&Tag::Byte(1)));
+    let mut cursor = Writer::new(vec![]);
+    Nbt(&block).encode(&mut cursor).unwrap();
+    let buf = cursor.get_raw_payload();
+    let Nbt(decoded) = Nbt::<Block>::decode(&mut Reader::new(&buf)).unwrap();
+    assert_eq!(decoded, block);
+}
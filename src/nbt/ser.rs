@@ -0,0 +1,252 @@
+use serde::{ser, Serialize};
+
+use super::{Compound, Error, Tag};
+
+/// Maps a serde type to a `Tag`. Structs and maps become compounds, sequences become lists,
+/// bools become bytes and unsigned ints the signed tag of the same width. `None` fields are
+/// left out.
+pub fn to_tag<T: Serialize + ?Sized>(value: &T) -> Result<Tag> {
+    match value.serialize(Serializer)? {
+        Some(p) => Ok(p),
+        None => Err(Error("nothing to serialize".to_owned())),
+    }
+}
+
+// `None` is a value that is left out of its compound
+struct Serializer;
+
+type Result<T = Option<Tag>> = std::result::Result<T, Error>;
+
+fn element<T: Serialize + ?Sized>(value: &T) -> Result<Tag> {
+    match value.serialize(Serializer)? {
+        Some(p) => Ok(p),
+        None => Err(Error("None in a list".to_owned())),
+    }
+}
+
+fn key<T: Serialize + ?Sized>(key: &T) -> Result<String> {
+    match key.serialize(Serializer)? {
+        Some(Tag::String(p)) => Ok(p),
+        _ => Err(Error("compound keys must be strings".to_owned())),
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Tag>;
+    type Error = Error;
+    type SerializeSeq = List;
+    type SerializeTuple = List;
+    type SerializeTupleStruct = List;
+    type SerializeTupleVariant = ser::Impossible<Option<Tag>, Error>;
+    type SerializeMap = Map;
+    type SerializeStruct = Map;
+    type SerializeStructVariant = ser::Impossible<Option<Tag>, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result {
+        Ok(Some(Tag::Byte(v as i8)))
+    }
+    fn serialize_i8(self, v: i8) -> Result {
+        Ok(Some(Tag::Byte(v)))
+    }
+    fn serialize_i16(self, v: i16) -> Result {
+        Ok(Some(Tag::Short(v)))
+    }
+    fn serialize_i32(self, v: i32) -> Result {
+        Ok(Some(Tag::Int(v)))
+    }
+    fn serialize_i64(self, v: i64) -> Result {
+        Ok(Some(Tag::Long(v)))
+    }
+    fn serialize_u8(self, v: u8) -> Result {
+        Ok(Some(Tag::Byte(v as i8)))
+    }
+    fn serialize_u16(self, v: u16) -> Result {
+        Ok(Some(Tag::Short(v as i16)))
+    }
+    fn serialize_u32(self, v: u32) -> Result {
+        Ok(Some(Tag::Int(v as i32)))
+    }
+    fn serialize_u64(self, v: u64) -> Result {
+        Ok(Some(Tag::Long(v as i64)))
+    }
+    fn serialize_f32(self, v: f32) -> Result {
+        Ok(Some(Tag::Float(v)))
+    }
+    fn serialize_f64(self, v: f64) -> Result {
+        Ok(Some(Tag::Double(v)))
+    }
+    fn serialize_char(self, v: char) -> Result {
+        Ok(Some(Tag::String(v.to_string())))
+    }
+    fn serialize_str(self, v: &str) -> Result {
+        Ok(Some(Tag::String(v.to_owned())))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result {
+        Ok(Some(Tag::ByteArray(v.to_vec())))
+    }
+    fn serialize_none(self) -> Result {
+        Ok(None)
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result {
+        value.serialize(self)
+    }
+    fn serialize_unit(self) -> Result {
+        Ok(None)
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result {
+        Ok(None)
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result {
+        Ok(Some(Tag::String(variant.to_owned())))
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result {
+        let mut compound = Compound::new();
+        if let Some(p) = value.serialize(Serializer)? {
+            compound.insert(variant.to_owned(), p);
+        }
+        Ok(Some(Tag::Compound(compound)))
+    }
+    fn serialize_seq(self, len: Option<usize>) -> Result<List> {
+        Ok(List(Vec::with_capacity(len.unwrap_or(0))))
+    }
+    fn serialize_tuple(self, len: usize) -> Result<List> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<List> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error(format!(
+            "unsupported tuple variant {}::{}",
+            name, variant
+        )))
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Map> {
+        Ok(Map(Compound::new(), None))
+    }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Map> {
+        self.serialize_map(None)
+    }
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error(format!(
+            "unsupported struct variant {}::{}",
+            name, variant
+        )))
+    }
+}
+
+struct List(Vec<Tag>);
+
+impl ser::SerializeSeq for List {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let tag = element(value)?;
+        if let Some(first) = self.0.first() {
+            if first.id() != tag.id() {
+                return Err(Error("list elements differ in type".to_owned()));
+            }
+        }
+        self.0.push(tag);
+        Ok(())
+    }
+    fn end(self) -> Result {
+        Ok(Some(Tag::List(self.0)))
+    }
+}
+
+impl ser::SerializeTuple for List {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for List {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+// the compound so far and the key waiting for its value
+struct Map(Compound, Option<String>);
+
+impl ser::SerializeMap for Map {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, k: &T) -> Result<()> {
+        self.1 = Some(key(k)?);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let k = match self.1.take() {
+            Some(p) => p,
+            None => return Err(Error("value without a key".to_owned())),
+        };
+        if let Some(p) = value.serialize(Serializer)? {
+            self.0.insert(k, p);
+        }
+        Ok(())
+    }
+    fn end(self) -> Result {
+        Ok(Some(Tag::Compound(self.0)))
+    }
+}
+
+impl ser::SerializeStruct for Map {
+    type Ok = Option<Tag>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, k: &'static str, value: &T) -> Result<()> {
+        if let Some(p) = value.serialize(Serializer)? {
+            self.0.insert(k.to_owned(), p);
+        }
+        Ok(())
+    }
+    fn end(self) -> Result {
+        Ok(Some(Tag::Compound(self.0)))
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time::timeout,
+};
+
+// longest request head read before giving up on a client
+const MAX_REQUEST: u64 = 8 * 1024;
+
+// how long a client has to send its request and to take the whole response, after which
+// the connection is closed
+#[derive(Clone, Copy)]
+struct Timeouts {
+    request: Duration,
+    response: Duration,
+}
+
+const TIMEOUTS: Timeouts = Timeouts {
+    request: Duration::from_secs(10),
+    response: Duration::from_secs(10 * 60),
+};
+
+/// Serves the files of a pack directory over plain HTTP, so `ResourcePack::url` can point at
+/// a local address during development. Only `GET` and `HEAD` of files directly in the
+/// directory are answered, and never `.key` files. Clients that take over 10 seconds to send
+/// their request or 10 minutes to download are dropped. Stops when dropped.
+pub struct PackServer {
+    address: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl PackServer {
+    pub async fn bind<P: Into<PathBuf>>(dir: P, address: SocketAddr) -> Result<Self> {
+        let dir = Arc::new(dir.into());
+        let socket = TcpListener::bind(address).await?;
+        let address = socket.local_addr()?;
+        let task = tokio::spawn(async move {
+            loop {
+                let stream = match socket.accept().await {
+                    Ok((p, _)) => p,
+                    Err(e) => {
+                        eprintln!("pack server accept error {}", e);
+                        continue;
+                    }
+                };
+                let dir = dir.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, &dir, TIMEOUTS).await {
+                        eprintln!("pack server error {}", e);
+                    }
+                });
+            }
+        });
+        Ok(Self { address, task })
+    }
+
+    /// Address the server is bound to, with the port picked if it was 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// URL of a file in the pack directory.
+    pub fn url(&self, file_name: &str) -> String {
+        format!("http://{}/{}", self.address, encode_path(file_name))
+    }
+}
+
+impl Drop for PackServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn encode_path(name: &str) -> String {
+    let mut encoded = String::new();
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode_path(path: &str) -> Option<String> {
+    let mut decoded = vec![];
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            decoded.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// file in `dir` a request path names, never anything outside of it or a pack's content key
+fn resolve(dir: &Path, path: &str) -> Option<PathBuf> {
+    let path = path.split(['?', '#']).next()?.strip_prefix('/')?;
+    let name = decode_path(path)?;
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.ends_with(".key")
+        || name.contains(['/', '\\'])
+    {
+        return None;
+    }
+    Some(dir.join(name))
+}
+
+async fn respond(stream: &mut TcpStream, status: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+async fn read_head<R: AsyncRead + Unpin>(stream: R) -> Result<String> {
+    let mut lines = BufReader::new(stream.take(MAX_REQUEST));
+    let mut request_line = String::new();
+    lines.read_line(&mut request_line).await?;
+    // skip the headers, nothing in them changes the response
+    loop {
+        let mut line = String::new();
+        if lines.read_line(&mut line).await? == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "incomplete request"));
+        }
+        if line == "\r\n" || line == "\n" {
+            return Ok(request_line);
+        }
+    }
+}
+
+async fn serve(mut stream: TcpStream, dir: &Path, timeouts: Timeouts) -> Result<()> {
+    let request_line = match timeout(timeouts.request, read_head(&mut stream)).await {
+        Ok(p) => p?,
+        Err(_) => return Err(Error::new(ErrorKind::TimedOut, "request timed out")),
+    };
+    match timeout(timeouts.response, answer(&mut stream, dir, &request_line)).await {
+        Ok(p) => p,
+        Err(_) => Err(Error::new(ErrorKind::TimedOut, "response timed out")),
+    }
+}
+
+async fn answer(stream: &mut TcpStream, dir: &Path, request_line: &str) -> Result<()> {
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(m), Some(p)) => (m, p),
+        _ => return respond(stream, "400 Bad Request").await,
+    };
+    if method != "GET" && method != "HEAD" {
+        return respond(stream, "405 Method Not Allowed").await;
+    }
+    let file = match resolve(dir, path) {
+        Some(p) => File::open(p).await.ok(),
+        None => None,
+    };
+    let mut file = match file {
+        Some(p) if p.metadata().await?.is_file() => p,
+        _ => return respond(stream, "404 Not Found").await,
+    };
+    let head = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/zip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        file.metadata().await?.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    if method == "GET" {
+        tokio::io::copy(&mut file, stream).await?;
+    }
+    stream.shutdown().await
+}
+
+#[tokio::test]
+async fn pack_server() {
+    let dir = std::env::temp_dir().join(format!("bers-pack-server-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("my pack.mcpack"), [1, 2, 3]).unwrap();
+    std::fs::write(dir.join("my pack.mcpack.key"), [4]).unwrap();
+    let server = PackServer::bind(&dir, "127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let url = server.url("my pack.mcpack");
+    assert_eq!(
+        url,
+        format!("http://{}/my%20pack.mcpack", server.local_addr())
+    );
+
+    let address = server.local_addr();
+    let get = |path: &'static str| async move {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await.unwrap();
+        response
+    };
+    let response = get("/my%20pack.mcpack").await;
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with(b"\r\n\r\n\x01\x02\x03"));
+    let response = get("/..%2Fetc%2Fpasswd").await;
+    assert!(response.starts_with(b"HTTP/1.1 404"));
+    let response = get("/my%20pack.mcpack.key").await;
+    assert!(response.starts_with(b"HTTP/1.1 404"));
+    let response = get("/missing.mcpack").await;
+    assert!(response.starts_with(b"HTTP/1.1 404"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn pack_server_timeout() {
+    let socket = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut client = TcpStream::connect(socket.local_addr().unwrap())
+        .await
+        .unwrap();
+    let (stream, _) = socket.accept().await.unwrap();
+    // a request that never ends
+    client.write_all(b"GET /a HTTP/1.1\r\n").await.unwrap();
+    let timeouts = Timeouts {
+        request: Duration::from_millis(50),
+        ..TIMEOUTS
+    };
+    let error = serve(stream, Path::new("."), timeouts).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::TimedOut);
+    // and the connection is closed
+    let mut rest = vec![];
+    assert_eq!(client.read_to_end(&mut rest).await.unwrap(), 0);
+}
@@ -0,0 +1,143 @@
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+use crate::{
+    encoding::{Decode, Encode},
+    reader::{self, DecodeError, Reader},
+    writer::Writer,
+};
+
+/// Batch compression negotiated through `NetworkSettings`. Bedrock calls raw deflate "zlib".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Zlib,
+    Snappy,
+    None,
+}
+
+impl Compression {
+    /// ID used by `NetworkSettings`.
+    pub fn id(self) -> u16 {
+        match self {
+            Compression::Zlib => 0,
+            Compression::Snappy => 1,
+            Compression::None => 0xffff,
+        }
+    }
+
+    pub fn from_id(id: u16) -> Option<Self> {
+        match id {
+            0 => Some(Compression::Zlib),
+            1 => Some(Compression::Snappy),
+            0xffff => Some(Compression::None),
+            _ => None,
+        }
+    }
+
+    /// Byte in front of every batch from 1.20.60.
+    pub fn prefix(self) -> u8 {
+        match self {
+            Compression::Zlib => 0x00,
+            Compression::Snappy => 0x01,
+            Compression::None => 0xff,
+        }
+    }
+
+    pub fn from_prefix(prefix: u8) -> Option<Self> {
+        match prefix {
+            0x00 => Some(Compression::Zlib),
+            0x01 => Some(Compression::Snappy),
+            0xff => Some(Compression::None),
+            _ => None,
+        }
+    }
+
+    pub fn compress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::Zlib => {
+                let mut compressor = DeflateEncoder::new(vec![], flate2::Compression::new(7));
+                compressor.write_all(data)?;
+                compressor.finish()
+            }
+            Compression::Snappy => match snap::raw::Encoder::new().compress_vec(data) {
+                Ok(p) => Ok(p),
+                Err(e) => Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+            },
+            Compression::None => Ok(data.to_vec()),
+        }
+    }
+
+    /// Fails once the output would grow past `max_size` bytes, without allocating more.
+    pub fn decompress(self, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+        let too_large = || {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("batch decompresses to over {} bytes", max_size),
+            )
+        };
+        match self {
+            Compression::Zlib => {
+                let mut decompressed = vec![];
+                DeflateDecoder::new(data)
+                    .take(max_size as u64 + 1)
+                    .read_to_end(&mut decompressed)?;
+                if decompressed.len() > max_size {
+                    return Err(too_large());
+                }
+                Ok(decompressed)
+            }
+            Compression::Snappy => {
+                let length = match snap::raw::decompress_len(data) {
+                    Ok(p) => p,
+                    Err(e) => return Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+                };
+                if length > max_size {
+                    return Err(too_large());
+                }
+                match snap::raw::Decoder::new().decompress_vec(data) {
+                    Ok(p) => Ok(p),
+                    Err(e) => Err(Error::new(ErrorKind::InvalidData, e.to_string())),
+                }
+            }
+            Compression::None if data.len() > max_size => Err(too_large()),
+            Compression::None => Ok(data.to_vec()),
+        }
+    }
+}
+
+/// As its `id`, a u16 LE.
+impl Encode for Compression {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        self.id().encode(cursor)
+    }
+}
+
+impl Decode for Compression {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        match Compression::from_id(u16::decode(cursor)?) {
+            Some(p) => Ok(p),
+            None => Err(DecodeError::InvalidValue(
+                "Unknown compression algorithm".to_owned(),
+            )),
+        }
+    }
+}
+
+#[test]
+fn compression() {
+    let data = b"bedrock bedrock bedrock bedrock bedrock".repeat(10);
+    for compression in [Compression::Zlib, Compression::Snappy, Compression::None] {
+        let compressed = compression.compress(&data).unwrap();
+        assert_eq!(
+            compression.decompress(&compressed, data.len()).unwrap(),
+            data
+        );
+        assert!(compression.decompress(&compressed, data.len() - 1).is_err());
+        assert_eq!(Compression::from_id(compression.id()), Some(compression));
+        assert_eq!(
+            Compression::from_prefix(compression.prefix()),
+            Some(compression)
+        );
+    }
+}
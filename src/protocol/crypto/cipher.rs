@@ -85,6 +85,8 @@ impl Cipher {
         let mut digest_bytes = digest.finish().as_ref()[..8].to_vec();
         payload.append(&mut digest_bytes);
 
+        self.send += 1;
+
         Ok(())
     }
 
@@ -96,9 +98,14 @@ impl Cipher {
         self.cipher.apply_keystream(payload);
     }
 
+    /// Decrypts and verifies the payload, leaving it without the trailing checksum.
     pub fn decode(&mut self, payload: &mut Vec<u8>) -> Result<(), CryptErr> {
+        if payload.len() < 8 {
+            return Err(CryptErr::BadPacket);
+        }
         self.decrypt(payload);
         self.check_sum(payload)?;
+        payload.truncate(payload.len() - 8);
         Ok(())
     }
 
@@ -43,9 +43,10 @@ impl KeyPair {
             Ok(p) => p,
             Err(e) => return Err(CryptErr::CryptoError(e)),
         };
-        let shared_secret =
-            ECDHNISTP384::<48>::generate_shared_secret(&self.private, &peer_pubkey).unwrap();
-        Ok(shared_secret.to_bytes().to_vec())
+        match ECDHNISTP384::<48>::generate_shared_secret(&self.private, &peer_pubkey) {
+            Ok(p) => Ok(p.to_bytes().to_vec()),
+            Err(e) => Err(CryptErr::CryptoError(e)),
+        }
     }
 
     pub fn ecdh_from_pem(&self, pubkey_pem: &str) -> Result<Vec<u8>, CryptErr> {
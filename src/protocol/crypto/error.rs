@@ -15,6 +15,7 @@ pub enum CryptErr {
     JwtDecodingError,
     JwtVerifyError,
     BadPacket,
+    InvalidChain(String),
     Other(String),
 }
 
@@ -32,6 +33,7 @@ impl fmt::Display for CryptErr {
             CryptErr::JwtDecodingError => write!(f, "Jwt format error"),
             CryptErr::JwtVerifyError => write!(f, "Jwt verify error"),
             CryptErr::BadPacket => write!(f, "BadPacket"),
+            CryptErr::InvalidChain(e) => write!(f, "InvalidChain {}", e),
             CryptErr::Other(e) => write!(f, "Other {}", e),
         }
     }
@@ -1,26 +1,29 @@
 use crate::protocol::crypto::cipher::Cipher;
 use crate::protocol::crypto::ecdsa::{KeyPair, PublicKey};
 use crate::protocol::crypto::error::CryptErr;
-use crate::protocol::crypto::jwt::Jwt;
+use crate::protocol::crypto::jwt::{Header, Jwt};
 
 use rand::Rng;
 use ring::digest;
-use serde_json::json;
+use serde_json::{json, Value};
+
+fn secret_key(salt: &[u8], shared_secret: &[u8]) -> digest::Digest {
+    let digest_alg = &digest::SHA256;
+    let mut digest = digest::Context::new(digest_alg);
+    digest.update(salt);
+    digest.update(shared_secret);
+    digest.finish()
+}
 
 pub fn exchange(pubkey: PublicKey) -> Result<(String, Cipher), CryptErr> {
     //jwt and IV
     let keypair = KeyPair::gen();
 
-    let shared_secret = keypair.ecdh(pubkey.bytes()).unwrap();
+    let shared_secret = keypair.ecdh(pubkey.bytes())?;
 
     let salt = rand::thread_rng().gen::<[u8; 16]>();
 
-    let digest_alg = &digest::SHA256;
-    let mut digest = digest::Context::new(digest_alg);
-    digest.update(&salt);
-    digest.update(&shared_secret);
-
-    let secret_key = digest.finish();
+    let secret_key = secret_key(&salt, &shared_secret);
 
     let claims = json!({
         "salt": base64::encode(&salt),
@@ -35,3 +38,45 @@ pub fn exchange(pubkey: PublicKey) -> Result<(String, Cipher), CryptErr> {
     let cipher = Cipher::new(secret_key.as_ref())?;
     Ok((jwt, cipher))
 }
+
+/// Client side of `exchange`. Verifies the server's handshake JWT and derives the same cipher.
+pub fn client_exchange(keypair: &KeyPair, handshake_jwt: &str) -> Result<Cipher, CryptErr> {
+    let server_key = PublicKey::from_pem(&Header::decode_header(handshake_jwt)?.x5u)?;
+    let token = Jwt::decode(handshake_jwt, &server_key)?;
+
+    let claims: Value = match serde_json::from_str(&token.payload) {
+        Ok(p) => p,
+        Err(e) => return Err(CryptErr::SerdeError(e)),
+    };
+    let salt = match claims.get("salt").and_then(|s| s.as_str()) {
+        Some(p) => p,
+        None => return Err(CryptErr::UnexceptedFormatError("no salt".to_owned())),
+    };
+    let salt = match base64::decode(salt) {
+        Ok(p) => p,
+        Err(e) => return Err(CryptErr::Base64Error(e)),
+    };
+
+    let shared_secret = keypair.ecdh(server_key.bytes())?;
+    Cipher::new(secret_key(&salt, &shared_secret).as_ref())
+}
+
+#[test]
+fn exchange_both_sides() {
+    let client_key = KeyPair::gen();
+    let (jwt, mut server) = exchange(client_key.public_key()).unwrap();
+    let mut client = client_exchange(&client_key, &jwt).unwrap();
+
+    // every batch has its own counter in the checksum
+    for i in 0..3u8 {
+        let mut payload = vec![b'c', i];
+        client.encode(&mut payload).unwrap();
+        server.decode(&mut payload).unwrap();
+        assert_eq!(payload, [b'c', i]);
+
+        let mut payload = vec![b's', i];
+        server.encode(&mut payload).unwrap();
+        client.decode(&mut payload).unwrap();
+        assert_eq!(payload, [b's', i]);
+    }
+}
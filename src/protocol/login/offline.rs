@@ -0,0 +1,88 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use serde_json::json;
+
+use crate::protocol::{
+    crypto::{ecdsa::KeyPair, error::CryptErr, jwt::Jwt},
+    types::player_data::{ExtraData, PlayerData},
+};
+
+use super::verify::Chain;
+
+// how long a self-signed chain stays valid, the same as the ones Mojang hands out
+const CHAIN_LIFETIME: u64 = 2 * 24 * 60 * 60;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The single chain link an offline client sends: `extra_data` and the public key of `keypair`,
+/// signed by `keypair` itself.
+pub fn self_signed_jwt(keypair: &KeyPair, extra_data: &ExtraData) -> Result<String, CryptErr> {
+    let now = now();
+    let claims = json!({
+        "certificateAuthority": true,
+        "exp": now + CHAIN_LIFETIME,
+        "nbf": now - 60,
+        "iat": now,
+        "randomNonce": rand::thread_rng().gen::<i64>(),
+        "identityPublicKey": keypair.export_public_key()?,
+        "extraData": extra_data,
+    });
+    Jwt::encode(claims.to_string(), keypair)
+}
+
+/// Chain JSON for `LoginPacket::chain`, holding only `self_signed_jwt`. Accepted by servers
+/// running `Auth::Offline`.
+pub fn self_signed_chain(keypair: &KeyPair, extra_data: &ExtraData) -> Result<String, CryptErr> {
+    let chain = Chain {
+        chain: vec![self_signed_jwt(keypair, extra_data)?],
+    };
+    match serde_json::to_string(&chain) {
+        Ok(p) => Ok(p),
+        Err(e) => Err(CryptErr::SerdeError(e)),
+    }
+}
+
+/// Skin JWT for `LoginPacket::player_data`, signed with the key the chain ends in.
+pub fn client_data_jwt(keypair: &KeyPair, client_data: &PlayerData) -> Result<String, CryptErr> {
+    match serde_json::to_string(client_data) {
+        Ok(p) => Jwt::encode(p, keypair),
+        Err(e) => Err(CryptErr::SerdeError(e)),
+    }
+}
+
+#[test]
+fn offline_chain() {
+    use super::verify::{Auth, Verifier};
+
+    let keypair = KeyPair::gen();
+    let extra_data = ExtraData {
+        xuid: "".to_owned(),
+        identity: "e2c7a8b4-4f2b-3c3c-9a4e-1d1b2e3f4a5b".to_owned(),
+        display_name: "Steve".to_owned(),
+        title_id: "".to_owned(),
+    };
+    let chain = self_signed_chain(&keypair, &extra_data).unwrap();
+
+    let online = Verifier::default();
+    assert!(online.verify(chain.clone()).is_err());
+
+    let offline = Verifier {
+        auth: Auth::Offline,
+        ..Verifier::default()
+    };
+    let (key, identity) = offline.verify(chain.clone()).unwrap();
+    assert_eq!(key.bytes(), keypair.public_key().bytes());
+    assert_eq!(identity.display_name, "Steve");
+
+    let custom = Verifier {
+        roots: vec![keypair.export_public_key().unwrap()],
+        ..Verifier::default()
+    };
+    assert!(custom.verify(chain).is_ok());
+}
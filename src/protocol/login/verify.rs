@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -12,75 +14,162 @@ use crate::protocol::{
 
 #[derive(Serialize, Deserialize)]
 pub struct Chain {
-    chain: Vec<String>,
+    pub chain: Vec<String>,
 }
 
-const MOJNG_PUBLIC_KEY : &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAE8ELkixyLcwlZryUQcu1TvPOmI2B7vX83ndnWRUaXm74wFfa5f/lwQNTfrLVHa2PmenpGI6JhIMUJaWZrjmMj90NoKNFSNBuKdm8rYiXsfaz3K36x/1U26HpG0ZxK/V1V";
-
-pub fn verify(chain: String) -> Result<(PublicKey, ExtraData), CryptErr> {
-    let chain: Chain = match serde_json::from_str(&chain) {
-        Ok(p) => p,
-        Err(e) => return Err(CryptErr::SerdeError(e)),
-    };
-
-    let first_key_header = Header::decode_header(&chain.chain[0])?;
+pub const MOJNG_PUBLIC_KEY : &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAE8ELkixyLcwlZryUQcu1TvPOmI2B7vX83ndnWRUaXm74wFfa5f/lwQNTfrLVHa2PmenpGI6JhIMUJaWZrjmMj90NoKNFSNBuKdm8rYiXsfaz3K36x/1U26HpG0ZxK/V1V";
 
-    let x5u = first_key_header.x5u;
-    let mut pubkey = PublicKey::from_pem(&x5u)?;
-    let mut verified = false;
+/// Which login chains the server accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Auth {
+    /// Chains signed by one of the trusted roots, i.e. players signed in to Xbox Live.
+    Online,
+    /// Also self-signed single-JWT chains. The XUID and identity are taken from the chain as is,
+    /// so anyone can claim any name. Meant for local testing and LAN play.
+    Offline,
+}
 
-    let mut final_key: Option<PublicKey> = None;
+/// Login chain checks.
+///
+/// A chain is a list of JWTs where each link is signed by the key the previous link certified
+/// in `identityPublicKey`. Xbox chains have three links: the client certifies Mojang's key,
+/// Mojang certifies an intermediate key, and the intermediate one certifies the client key
+/// along with the player's `extraData`.
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    pub auth: Auth,
+    /// Keys allowed to sign the root link, base64 DER as found in `x5u`. Holds Mojang's key by
+    /// default; add the new one here when Mojang rotates it.
+    pub roots: Vec<String>,
+    /// Tolerated clock difference with the issuer, in seconds, when checking `exp`, `nbf`
+    /// and `iat`.
+    pub clock_skew: u64,
+    /// Longest chain accepted.
+    pub max_chain_length: usize,
+}
 
-    let mut extra_data: Option<ExtraData> = None;
+impl Default for Verifier {
+    fn default() -> Self {
+        Self {
+            auth: Auth::Online,
+            roots: vec![MOJNG_PUBLIC_KEY.to_owned()],
+            clock_skew: 60,
+            max_chain_length: 3,
+        }
+    }
+}
 
-    for jwt in &chain.chain {
-        let token = Jwt::decode(jwt, &pubkey)?;
+fn invalid(message: String) -> CryptErr {
+    CryptErr::InvalidChain(message)
+}
 
-        let x5u = Header::decode_header(jwt)?.x5u;
+fn time_claim(claims: &Value, name: &str) -> Result<Option<i64>, String> {
+    match claims.get(name) {
+        Some(p) => match p.as_i64() {
+            Some(p) => Ok(Some(p)),
+            None => Err(format!("has a {} that is not a number", name)),
+        },
+        None => Ok(None),
+    }
+}
 
-        if x5u == MOJNG_PUBLIC_KEY {
-            verified = true;
-        }
+impl Verifier {
+    /// Verifies the chain of a `LoginPacket` against the current time. Returns the client's
+    /// public key, which signs the client data JWT, and the player's identity.
+    pub fn verify(&self, chain: String) -> Result<(PublicKey, ExtraData), CryptErr> {
+        let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(p) => p.as_secs() as i64,
+            Err(e) => return Err(CryptErr::Other(e.to_string())),
+        };
+        self.verify_at(chain, now)
+    }
 
-        let claims: Value = match serde_json::from_str(&token.payload) {
+    pub fn verify_at(&self, chain: String, now: i64) -> Result<(PublicKey, ExtraData), CryptErr> {
+        let chain: Chain = match serde_json::from_str(&chain) {
             Ok(p) => p,
             Err(e) => return Err(CryptErr::SerdeError(e)),
         };
+        let links = chain.chain;
+        if links.is_empty() || links.len() > self.max_chain_length {
+            return Err(invalid(format!("chain has {} links", links.len())));
+        }
+
+        // the first link is signed by the key it names; every later one by the key certified before it
+        let first_key = Header::decode_header(&links[0])?.x5u;
+        let mut key = first_key.clone();
+        let mut root_link: Option<usize> = None;
+        let mut extra_data: Option<ExtraData> = None;
 
-        let identity_public_key = match claims.get("identityPublicKey") {
-            Some(p) => p,
-            None => {
-                return Err(CryptErr::UnexceptedFormatError(
-                    "no identityPublicKey".to_string(),
-                ))
+        for (i, jwt) in links.iter().enumerate() {
+            if Header::decode_header(jwt)?.x5u != key {
+                return Err(invalid(format!("link {} names the wrong signing key", i)));
             }
-        };
+            let token = Jwt::decode(jwt, &PublicKey::from_pem(&key)?)?;
 
-        let identity_public_key_str = match identity_public_key.as_str() {
-            Some(p) => p,
-            None => {
-                return Err(CryptErr::UnexceptedFormatError(
-                    "identityPublicKey is not string".to_string(),
-                ))
+            if self.roots.contains(&key) {
+                if root_link.is_some() {
+                    return Err(invalid("root key signs more than one link".to_owned()));
+                }
+                root_link = Some(i);
             }
-        };
 
-        pubkey = PublicKey::from_pem(identity_public_key_str)?;
+            let claims: Value = match serde_json::from_str(&token.payload) {
+                Ok(p) => p,
+                Err(e) => return Err(CryptErr::SerdeError(e)),
+            };
+            self.check_time(&claims, now)
+                .map_err(|e| invalid(format!("link {} {}", i, e)))?;
+
+            key = match claims.get("identityPublicKey").and_then(|p| p.as_str()) {
+                Some(p) => p.to_owned(),
+                None => {
+                    return Err(CryptErr::UnexceptedFormatError(
+                        "no identityPublicKey".to_string(),
+                    ))
+                }
+            };
+
+            if let Some(data) = claims.get("extraData") {
+                // only the last link vouches for the player
+                if i != links.len() - 1 {
+                    return Err(invalid(format!("extraData in link {}", i)));
+                }
+                extra_data = ExtraData::from_value(data);
+            }
+        }
 
-        if let Some(data) = claims.get("extraData") {
-            extra_data = ExtraData::from_value(data);
+        match root_link {
+            // the client's own link may come first to certify the root key
+            Some(0) | Some(1) => {}
+            Some(i) => return Err(invalid(format!("root key signs link {}", i))),
+            // an offline chain is one JWT certifying the very key that signed it
+            None if self.auth == Auth::Offline && links.len() == 1 && key == first_key => {}
+            None => return Err(invalid("not signed by a trusted root".to_owned())),
         }
 
-        final_key = Some(pubkey.clone());
+        match extra_data {
+            Some(data) => Ok((PublicKey::from_pem(&key)?, data)),
+            None => Err(CryptErr::UnexceptedFormatError("no extraData".to_owned())),
+        }
     }
 
-    if !verified {
-        return Err(CryptErr::UnexceptedFormatError(
-            "Unexcepted chain".to_owned(),
-        ));
+    fn check_time(&self, claims: &Value, now: i64) -> Result<(), String> {
+        let skew = self.clock_skew as i64;
+        match time_claim(claims, "exp")? {
+            Some(exp) if now > exp + skew => return Err("expired".to_owned()),
+            Some(_) => {}
+            None => return Err("has no exp".to_owned()),
+        }
+        match time_claim(claims, "nbf")? {
+            Some(nbf) if now + skew < nbf => return Err("is not valid yet".to_owned()),
+            Some(_) => {}
+            None => return Err("has no nbf".to_owned()),
+        }
+        match time_claim(claims, "iat")? {
+            Some(iat) if iat > now + skew => Err("was issued in the future".to_owned()),
+            _ => Ok(()),
+        }
     }
-
-    Ok((final_key.unwrap(), extra_data.unwrap()))
 }
 
 pub fn verify_skin(skin_jwt: String, pubkey: &PublicKey) -> Result<PlayerData, CryptErr> {
@@ -92,3 +181,73 @@ pub fn verify_skin(skin_jwt: String, pubkey: &PublicKey) -> Result<PlayerData, C
 
     Ok(player_data)
 }
+
+#[test]
+fn chain_checks() {
+    use crate::protocol::crypto::ecdsa::KeyPair;
+    use serde_json::json;
+
+    let (client, root, intermediate) = (KeyPair::gen(), KeyPair::gen(), KeyPair::gen());
+    let now = 1_700_000_000;
+    let link = |signer: &KeyPair, certified: &KeyPair, extra_data: bool| {
+        let mut claims = json!({
+            "exp": now + 3600,
+            "nbf": now - 60,
+            "iat": now,
+            "identityPublicKey": certified.export_public_key().unwrap(),
+        });
+        if extra_data {
+            claims["extraData"] = json!({
+                "XUID": "2535400000000000",
+                "identity": "e2c7a8b4-4f2b-3c3c-9a4e-1d1b2e3f4a5b",
+                "displayName": "Steve",
+                "titleId": "896928775",
+            });
+        }
+        Jwt::encode(claims.to_string(), signer).unwrap()
+    };
+    let chain = |links: Vec<String>| serde_json::to_string(&Chain { chain: links }).unwrap();
+
+    let xbox = chain(vec![
+        link(&client, &root, false),
+        link(&root, &intermediate, false),
+        link(&intermediate, &client, true),
+    ]);
+    let verifier = Verifier {
+        roots: vec![root.export_public_key().unwrap()],
+        ..Verifier::default()
+    };
+    let (key, identity) = verifier.verify_at(xbox.clone(), now).unwrap();
+    assert_eq!(key.bytes(), client.public_key().bytes());
+    assert_eq!(identity.xuid, "2535400000000000");
+
+    // replayed after it expired, or presented before it is valid
+    assert!(verifier.verify_at(xbox.clone(), now + 3600 + 61).is_err());
+    assert!(verifier.verify_at(xbox.clone(), now - 200).is_err());
+    assert!(verifier.verify_at(xbox.clone(), now + 3600 + 30).is_ok());
+
+    // Mojang's key is not trusted for this root
+    assert!(Verifier::default().verify_at(xbox.clone(), now).is_err());
+
+    let short = Verifier {
+        max_chain_length: 2,
+        ..verifier.clone()
+    };
+    assert!(short.verify_at(xbox, now).is_err());
+
+    // the root link repeated further down the chain
+    let repeated = chain(vec![
+        link(&client, &root, false),
+        link(&root, &root, false),
+        link(&root, &client, true),
+    ]);
+    assert!(verifier.verify_at(repeated, now).is_err());
+
+    // extraData slipped into a link before the last one
+    let early = chain(vec![
+        link(&client, &root, false),
+        link(&root, &intermediate, true),
+        link(&intermediate, &client, false),
+    ]);
+    assert!(verifier.verify_at(early, now).is_err());
+}
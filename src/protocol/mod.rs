@@ -1,4 +1,6 @@
+pub mod compression;
 pub mod crypto;
 pub mod login;
 pub mod packets;
 pub mod types;
+pub mod version;
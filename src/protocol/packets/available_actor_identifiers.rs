@@ -0,0 +1,11 @@
+use crate::nbt::Tag;
+
+use super::Packet;
+
+/// Sent after `StartGame`.
+#[derive(Clone, Debug, PartialEq, Packet)]
+#[packet(id = 0x77)]
+pub struct AvailableActorIdentifiers {
+    /// A compound with the entity list under `idlist`.
+    pub identifiers: Tag,
+}
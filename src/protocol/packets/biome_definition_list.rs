@@ -0,0 +1,11 @@
+use crate::nbt::Tag;
+
+use super::Packet;
+
+/// Sent after `StartGame`.
+#[derive(Clone, Debug, PartialEq, Packet)]
+#[packet(id = 0x7a)]
+pub struct BiomeDefinitionList {
+    /// A compound of biome names to their definitions.
+    pub definitions: Tag,
+}
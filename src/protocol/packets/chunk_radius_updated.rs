@@ -0,0 +1,8 @@
+use super::Packet;
+
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x46)]
+pub struct ChunkRadiusUpdated {
+    #[packet(varint)]
+    pub chunk_radius: i32,
+}
@@ -0,0 +1,7 @@
+use super::Packet;
+
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x81)]
+pub struct ClientCacheStatus {
+    pub enabled: bool,
+}
@@ -0,0 +1,20 @@
+use crate::{
+    encoding::{Decode, Encode},
+    protocol::types::item::ItemInstance,
+};
+
+use super::Packet;
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct CreativeItem {
+    #[packet(varint)]
+    pub network_id: u32,
+    pub item: ItemInstance,
+}
+
+/// The creative inventory, sent after `StartGame`.
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x91)]
+pub struct CreativeContent {
+    pub items: Vec<CreativeItem>,
+}
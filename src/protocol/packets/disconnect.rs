@@ -1,29 +1,229 @@
-use crate::{reader::Reader, writer::Writer};
+use crate::{
+    encoding::{Decode, Encode, VarI32},
+    protocol::version::V1_20_40,
+    reader::{self, Reader},
+    writer::Writer,
+};
 
 use super::Packet;
 
-#[derive(Clone)]
+/// Why a connection was closed. Sent to the client by protocols from 1.20.40 on,
+/// and always reported to the embedder through `server::Event::Disconnected`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Unknown = 0,
+    CantConnectNoInternet = 1,
+    NoPermissions = 2,
+    UnrecoverableError = 3,
+    ThirdPartyBlocked = 4,
+    ThirdPartyNoInternet = 5,
+    ThirdPartyBadIp = 6,
+    ThirdPartyNoServerOrServerLocked = 7,
+    VersionMismatch = 8,
+    SkinIssue = 9,
+    InviteSessionNotFound = 10,
+    EduLevelSettingsMissing = 11,
+    LocalServerNotFound = 12,
+    LegacyDisconnect = 13,
+    UserLeaveGameAttempted = 14,
+    PlatformLockedSkinsError = 15,
+    RealmsWorldUnassigned = 16,
+    RealmsServerCantConnect = 17,
+    RealmsServerHidden = 18,
+    RealmsServerDisabledBeta = 19,
+    RealmsServerDisabled = 20,
+    CrossPlatformDisallowed = 21,
+    CantConnect = 22,
+    SessionNotFound = 23,
+    ClientSettingsIncompatibleWithServer = 24,
+    ServerFull = 25,
+    InvalidPlatformSkin = 26,
+    EditionVersionMismatch = 27,
+    EditionMismatch = 28,
+    LevelNewerThanExeVersion = 29,
+    NoFailOccurred = 30,
+    BannedSkin = 31,
+    Timeout = 32,
+    ServerNotFound = 33,
+    OutdatedServer = 34,
+    OutdatedClient = 35,
+    NoPremiumPlatform = 36,
+    MultiplayerDisabled = 37,
+    NoWifi = 38,
+    WorldCorruption = 39,
+    NoReason = 40,
+    Disconnected = 41,
+    InvalidPlayer = 42,
+    LoggedInOtherLocation = 43,
+    ServerIdConflict = 44,
+    NotAllowed = 45,
+    NotAuthenticated = 46,
+    InvalidTenant = 47,
+    UnknownPacket = 48,
+    UnexpectedPacket = 49,
+    InvalidCommandRequestPacket = 50,
+    HostSuspended = 51,
+    LoginPacketNoRequest = 52,
+    LoginPacketNoCert = 53,
+    MissingClient = 54,
+    Kicked = 55,
+    KickedForExploit = 56,
+    KickedForIdle = 57,
+    ResourcePackProblem = 58,
+    IncompatiblePack = 59,
+    OutOfStorage = 60,
+    InvalidLevel = 61,
+    DisconnectPacketDeprecated = 62,
+    BlockMismatch = 63,
+    InvalidHeights = 64,
+    InvalidWidths = 65,
+    ConnectionLost = 66,
+    ZombieConnection = 67,
+    Shutdown = 68,
+    ReasonNotSet = 69,
+    LoadingStateTimeout = 70,
+    ResourcePackLoadingFailed = 71,
+    SearchingForSessionLoadingScreenFailed = 72,
+}
+
+impl DisconnectReason {
+    const ALL: [Self; 73] = [
+        Self::Unknown,
+        Self::CantConnectNoInternet,
+        Self::NoPermissions,
+        Self::UnrecoverableError,
+        Self::ThirdPartyBlocked,
+        Self::ThirdPartyNoInternet,
+        Self::ThirdPartyBadIp,
+        Self::ThirdPartyNoServerOrServerLocked,
+        Self::VersionMismatch,
+        Self::SkinIssue,
+        Self::InviteSessionNotFound,
+        Self::EduLevelSettingsMissing,
+        Self::LocalServerNotFound,
+        Self::LegacyDisconnect,
+        Self::UserLeaveGameAttempted,
+        Self::PlatformLockedSkinsError,
+        Self::RealmsWorldUnassigned,
+        Self::RealmsServerCantConnect,
+        Self::RealmsServerHidden,
+        Self::RealmsServerDisabledBeta,
+        Self::RealmsServerDisabled,
+        Self::CrossPlatformDisallowed,
+        Self::CantConnect,
+        Self::SessionNotFound,
+        Self::ClientSettingsIncompatibleWithServer,
+        Self::ServerFull,
+        Self::InvalidPlatformSkin,
+        Self::EditionVersionMismatch,
+        Self::EditionMismatch,
+        Self::LevelNewerThanExeVersion,
+        Self::NoFailOccurred,
+        Self::BannedSkin,
+        Self::Timeout,
+        Self::ServerNotFound,
+        Self::OutdatedServer,
+        Self::OutdatedClient,
+        Self::NoPremiumPlatform,
+        Self::MultiplayerDisabled,
+        Self::NoWifi,
+        Self::WorldCorruption,
+        Self::NoReason,
+        Self::Disconnected,
+        Self::InvalidPlayer,
+        Self::LoggedInOtherLocation,
+        Self::ServerIdConflict,
+        Self::NotAllowed,
+        Self::NotAuthenticated,
+        Self::InvalidTenant,
+        Self::UnknownPacket,
+        Self::UnexpectedPacket,
+        Self::InvalidCommandRequestPacket,
+        Self::HostSuspended,
+        Self::LoginPacketNoRequest,
+        Self::LoginPacketNoCert,
+        Self::MissingClient,
+        Self::Kicked,
+        Self::KickedForExploit,
+        Self::KickedForIdle,
+        Self::ResourcePackProblem,
+        Self::IncompatiblePack,
+        Self::OutOfStorage,
+        Self::InvalidLevel,
+        Self::DisconnectPacketDeprecated,
+        Self::BlockMismatch,
+        Self::InvalidHeights,
+        Self::InvalidWidths,
+        Self::ConnectionLost,
+        Self::ZombieConnection,
+        Self::Shutdown,
+        Self::ReasonNotSet,
+        Self::LoadingStateTimeout,
+        Self::ResourcePackLoadingFailed,
+        Self::SearchingForSessionLoadingScreenFailed,
+    ];
+
+    /// Unknown codes from newer clients map to `Unknown`.
+    pub fn from_i32(v: i32) -> Self {
+        match usize::try_from(v) {
+            Ok(i) if i < Self::ALL.len() => Self::ALL[i],
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Disconnect {
+    /// Only on the wire from 1.20.40, older protocols read it back as `Unknown`.
+    pub reason: DisconnectReason,
     pub hide_kick_message: bool,
     pub kick_message: String,
 }
 
 impl Packet for Disconnect {
-    const ID: u8 = 0x5;
-
-    fn read(buf: &[u8]) -> std::io::Result<Self> {
-        let mut cursor = Reader::new(buf);
+    const ID: u16 = 0x5;
 
+    fn read(cursor: &mut Reader, protocol: u32) -> reader::Result<Self> {
+        let reason = if protocol >= V1_20_40 {
+            DisconnectReason::from_i32(VarI32::decode(cursor)?.0)
+        } else {
+            DisconnectReason::Unknown
+        };
+        let hide_kick_message = bool::decode(cursor)?;
+        let kick_message = if hide_kick_message {
+            String::new()
+        } else {
+            String::decode(cursor)?
+        };
         Ok(Self {
-            hide_kick_message: cursor.read_u8()? != 0,
-            kick_message: cursor.read_string()?,
+            reason,
+            hide_kick_message,
+            kick_message,
         })
     }
 
-    fn write(&self) -> std::io::Result<Vec<u8>> {
+    fn write(&self, protocol: u32) -> std::io::Result<Vec<u8>> {
         let mut cursor = Writer::new(vec![]);
-        cursor.write_u8(self.hide_kick_message as u8)?;
-        cursor.write_string(&self.kick_message)?;
+        if protocol >= V1_20_40 {
+            VarI32(self.reason as i32).encode(&mut cursor)?;
+        }
+        self.hide_kick_message.encode(&mut cursor)?;
+        if !self.hide_kick_message {
+            self.kick_message.encode(&mut cursor)?;
+        }
         Ok(cursor.get_raw_payload())
     }
 }
+
+#[test]
+fn disconnect_reason() {
+    for (i, reason) in DisconnectReason::ALL.iter().enumerate() {
+        assert_eq!(*reason as i32, i as i32);
+        assert_eq!(DisconnectReason::from_i32(i as i32), *reason);
+    }
+    assert_eq!(DisconnectReason::from_i32(-1), DisconnectReason::Unknown);
+    assert_eq!(
+        DisconnectReason::from_i32(10_000),
+        DisconnectReason::Unknown
+    );
+}
@@ -0,0 +1,105 @@
+use std::io::Result;
+
+use crate::{
+    encoding::{Decode, Encode, VarI32, VarU32},
+    protocol::{types::position::ChunkPos, version::V1_20_60},
+    reader::{self, Reader},
+    world::chunk::Chunk,
+    writer::Writer,
+};
+
+use super::Packet;
+
+// sub-chunk counts standing for the sub-chunk request modes
+const LIMITLESS: u32 = u32::MAX;
+const LIMITED: u32 = u32::MAX - 1;
+
+/// How many sub-chunks the payload holds, or how the client asks for them instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubChunkCount {
+    Count(u32),
+    /// The client requests every sub-chunk with `SubChunkRequest`.
+    Limitless,
+    /// The client requests sub-chunks up to this index.
+    Limited(u16),
+}
+
+/// A chunk column, see `world::chunk` for the payload.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelChunk {
+    pub position: ChunkPos,
+    /// Not on the wire before 1.20.60, the client assumes the overworld.
+    pub dimension: i32,
+    pub sub_chunk_count: SubChunkCount,
+    /// Hashes of the blobs the payload is made of, with the client blob cache enabled.
+    pub blob_hashes: Option<Vec<u64>>,
+    pub payload: Vec<u8>,
+}
+
+impl LevelChunk {
+    /// Encodes a whole chunk into the payload.
+    pub fn new(chunk: &Chunk, dimension: i32) -> Result<Self> {
+        let (count, payload) = chunk.network_encode()?;
+        Ok(Self {
+            position: chunk.position,
+            dimension,
+            sub_chunk_count: SubChunkCount::Count(count),
+            blob_hashes: None,
+            payload,
+        })
+    }
+}
+
+impl Packet for LevelChunk {
+    const ID: u16 = 0x3a;
+
+    fn read(cursor: &mut Reader, protocol: u32) -> reader::Result<Self> {
+        let position = ChunkPos::decode(cursor)?;
+        let dimension = if protocol >= V1_20_60 {
+            VarI32::decode(cursor)?.0
+        } else {
+            0
+        };
+        let sub_chunk_count = match VarU32::decode(cursor)?.0 {
+            LIMITLESS => SubChunkCount::Limitless,
+            LIMITED => SubChunkCount::Limited(u16::decode(cursor)?),
+            count => SubChunkCount::Count(count),
+        };
+        Ok(Self {
+            position,
+            dimension,
+            sub_chunk_count,
+            blob_hashes: if bool::decode(cursor)? {
+                Some(Vec::decode(cursor)?)
+            } else {
+                None
+            },
+            payload: Vec::decode(cursor)?,
+        })
+    }
+
+    fn write(&self, protocol: u32) -> Result<Vec<u8>> {
+        let mut cursor = Writer::new(vec![]);
+        self.position.encode(&mut cursor)?;
+        if protocol >= V1_20_60 {
+            VarI32(self.dimension).encode(&mut cursor)?;
+        }
+        match self.sub_chunk_count {
+            SubChunkCount::Count(count) => VarU32(count).encode(&mut cursor)?,
+            SubChunkCount::Limitless => VarU32(LIMITLESS).encode(&mut cursor)?,
+            SubChunkCount::Limited(highest) => {
+                VarU32(LIMITED).encode(&mut cursor)?;
+                highest.encode(&mut cursor)?;
+            }
+        }
+        match &self.blob_hashes {
+            Some(hashes) => {
+                true.encode(&mut cursor)?;
+                hashes.encode(&mut cursor)?;
+            }
+            None => false.encode(&mut cursor)?,
+        }
+        self.payload.encode(&mut cursor)?;
+        Ok(cursor.get_raw_payload())
+    }
+}
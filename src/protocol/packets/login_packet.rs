@@ -1,29 +1,61 @@
-use crate::reader::{Endian, Reader};
+use crate::{
+    encoding::{Be, Decode, Encode, LeString, VarU32},
+    reader::{self, Reader},
+    writer::Writer,
+};
 
 use super::Packet;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct LoginPacket {
     pub protocol_version: u32,
     pub chain: String,
     pub player_data: String,
 }
 
+/// The connection request shared with `SubClientLogin`: the chain JSON and the client data JWT,
+/// each with a u32 LE length, inside one varuint32-prefixed blob.
+pub(super) fn read_request(cursor: &mut Reader) -> reader::Result<(String, String)> {
+    let length = cursor.read_varu32()?;
+    let length = cursor.check_length(length.into())?;
+    let mut request = Reader::new(cursor.read_bytes(length)?);
+    let LeString(chain) = Decode::decode(&mut request)?;
+    let LeString(player_data) = Decode::decode(&mut request)?;
+    request.finish()?;
+    Ok((chain, player_data))
+}
+
+pub(super) fn write_request(
+    cursor: &mut Writer,
+    chain: &str,
+    player_data: &str,
+) -> std::io::Result<()> {
+    let mut request = Writer::new(vec![]);
+    LeString(chain).encode(&mut request)?;
+    LeString(player_data).encode(&mut request)?;
+    let request = request.get_raw_payload();
+
+    VarU32(request.len() as u32).encode(cursor)?;
+    cursor.write(&request)
+}
+
 impl Packet for LoginPacket {
-    const ID: u8 = 0x1;
+    const ID: u16 = 0x1;
 
-    fn read(buf: &[u8]) -> std::io::Result<Self> {
-        let mut cursor = Reader::new(buf);
-        let protocol_version = cursor.read_u32(Endian::Big)?;
-        let _data_length = cursor.read_varu32()?;
+    fn read(cursor: &mut Reader, _protocol: u32) -> reader::Result<Self> {
+        let Be(protocol_version) = Decode::decode(cursor)?;
+        let (chain, player_data) = read_request(cursor)?;
         Ok(Self {
             protocol_version,
-            chain: cursor.read_string()?,
-            player_data: cursor.read_string()?,
+            chain,
+            player_data,
         })
     }
 
-    fn write(&self) -> std::io::Result<Vec<u8>> {
-        Ok(vec![])
+    fn write(&self, _protocol: u32) -> std::io::Result<Vec<u8>> {
+        let mut cursor = Writer::new(vec![]);
+        Be(self.protocol_version).encode(&mut cursor)?;
+        write_request(&mut cursor, &self.chain, &self.player_data)?;
+        Ok(cursor.get_raw_payload())
     }
 }
@@ -1,24 +1,579 @@
+pub mod available_actor_identifiers;
+pub mod biome_definition_list;
+pub mod chunk_radius_updated;
+pub mod client_cache_status;
 pub mod client_to_server_handshake;
+pub mod creative_content;
 pub mod disconnect;
+pub mod level_chunk;
 pub mod login_packet;
+pub mod network_chunk_publisher_update;
+pub mod network_settings;
 pub mod play_status;
-pub mod server_to_client_handshake;
+pub mod request_chunk_radius;
+pub mod request_network_settings;
+pub mod resource_pack_chunk_data;
+pub mod resource_pack_chunk_request;
+pub mod resource_pack_client_response;
+pub mod resource_pack_data_info;
 pub mod resource_pack_stack;
 pub mod resource_packs_info;
+pub mod server_to_client_handshake;
+pub mod set_local_player_as_initialized;
+pub mod start_game;
+pub mod sub_client_login;
 use std::io::Result;
 
+pub use bers_derive::Packet;
+
+use crate::{
+    encoding::{Decode, Encode, VarU32},
+    reader::{self, DecodeError, Reader},
+    writer::Writer,
+};
+
+/// The varuint32 in front of every game packet. It packs the packet ID in its low 10 bits,
+/// then the sender and target sub-client in 2 bits each. Sub-client 0 is the player who opened
+/// the connection, 1 to 3 are split-screen players on the same device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Header {
+    pub id: u16,
+    pub sender: u8,
+    pub target: u8,
+}
+
+impl Header {
+    pub fn new(id: u16) -> Self {
+        Self {
+            id,
+            sender: 0,
+            target: 0,
+        }
+    }
+
+    /// Splits an encoded packet into its header and body.
+    pub fn read(buf: &[u8]) -> reader::Result<(Self, &[u8])> {
+        let mut cursor = Reader::new(buf);
+        let header = Self::read_from(&mut cursor)?;
+        Ok((header, &buf[buf.len() - cursor.remaining()..]))
+    }
+
+    pub fn read_from(cursor: &mut Reader) -> reader::Result<Self> {
+        let VarU32(header) = VarU32::decode(cursor)?;
+        Ok(Self {
+            id: (header & 0x3ff) as u16,
+            sender: ((header >> 10) & 0x3) as u8,
+            target: ((header >> 12) & 0x3) as u8,
+        })
+    }
+
+    pub fn write(&self) -> Result<Vec<u8>> {
+        let header = (self.id as u32 & 0x3ff)
+            | (self.sender as u32 & 0x3) << 10
+            | (self.target as u32 & 0x3) << 12;
+        let mut cursor = Writer::new(vec![]);
+        VarU32(header).encode(&mut cursor)?;
+        Ok(cursor.get_raw_payload())
+    }
+}
+
+/// A game packet. `protocol` is the session's protocol number (see `protocol::version`);
+/// packets whose layout changed between releases branch on it. Most packets derive it, see
+/// `bers_derive` for the field attributes.
 pub trait Packet: Clone {
-    const ID: u8;
-    fn read(buf: &[u8]) -> Result<Self>
+    const ID: u16;
+    fn read(cursor: &mut Reader, protocol: u32) -> reader::Result<Self>
     where
         Self: Sized;
-    fn write(&self) -> Result<Vec<u8>>;
+    fn write(&self, protocol: u32) -> Result<Vec<u8>>;
+}
+
+pub fn encode<T: Packet>(packet: T, protocol: u32) -> Result<Vec<u8>> {
+    encode_for(packet, protocol, 0, 0)
+}
+
+/// Encodes a packet between sub-clients, see `Header`.
+pub fn encode_for<T: Packet>(packet: T, protocol: u32, sender: u8, target: u8) -> Result<Vec<u8>> {
+    let header = Header {
+        id: T::ID,
+        sender,
+        target,
+    };
+    Ok([header.write()?, packet.write(protocol)?].concat())
 }
 
-pub fn encode<T: Packet>(packet: T) -> Result<Vec<u8>> {
-    Ok([&[T::ID], &*packet.write()?].concat())
+/// Decodes a whole packet, header included. Bytes left after the packet are an error.
+pub fn decode<T: Packet>(buf: &[u8], protocol: u32) -> reader::Result<T> {
+    let mut cursor = Reader::new(buf);
+    let header = Header::read_from(&mut cursor)?;
+    if header.id != T::ID {
+        return Err(DecodeError::InvalidValue(format!(
+            "expected packet ID {} but got {}",
+            T::ID,
+            header.id
+        )));
+    }
+    let packet = T::read(&mut cursor, protocol)?;
+    cursor.finish()?;
+    Ok(packet)
 }
 
-pub fn decode<T: Packet>(buf: &[u8]) -> Result<T> {
-    T::read(&buf[1..])
+#[cfg(test)]
+fn round_trip<T: Packet + PartialEq + std::fmt::Debug>(packet: T, protocol: u32) {
+    let buf = encode_for(packet.clone(), protocol, 1, 2).unwrap();
+    assert_eq!(
+        Header::read(&buf).unwrap().0,
+        Header {
+            id: T::ID,
+            sender: 1,
+            target: 2
+        }
+    );
+    assert_eq!(
+        decode::<T>(&buf, protocol).unwrap(),
+        packet,
+        "protocol {}",
+        protocol
+    );
+}
+
+// a `StartGame` using every field the protocol puts on the wire
+#[cfg(test)]
+fn start_game(protocol: u32) -> start_game::StartGame {
+    use crate::{
+        nbt::{Compound, Tag},
+        protocol::{
+            types::{
+                position::{BlockPos, Vec3},
+                uuid::Uuid,
+            },
+            version::*,
+        },
+    };
+    use start_game::*;
+
+    let mut properties = Compound::new();
+    properties.insert("states".to_owned(), Tag::List(vec![]));
+    StartGame {
+        entity_id: -5,
+        runtime_id: 5,
+        player_game_mode: 1,
+        position: Vec3 {
+            x: 1.5,
+            y: 70.0,
+            z: -2.5,
+        },
+        pitch: 10.0,
+        yaw: 90.0,
+        settings: LevelSettings {
+            seed: -1234,
+            custom_biome_name: "plains".to_owned(),
+            spawn: BlockPos { x: -8, y: 70, z: 8 },
+            editor_world_type: match protocol {
+                p if p >= V1_19_80 => 2,
+                p if p >= V1_19_10 => 1,
+                _ => 0,
+            },
+            created_in_editor: protocol >= V1_19_70,
+            exported_from_editor: protocol >= V1_19_70,
+            day_cycle_lock_time: if protocol >= V1_20_0 { 6000 } else { 0 },
+            rain_level: 0.5,
+            game_rules: vec![
+                GameRule {
+                    name: "dodaylightcycle".to_owned(),
+                    editable: true,
+                    value: GameRuleValue::Bool(false),
+                },
+                GameRule {
+                    name: "spawnradius".to_owned(),
+                    editable: false,
+                    value: GameRuleValue::Int(5),
+                },
+                GameRule {
+                    name: "float".to_owned(),
+                    editable: false,
+                    value: GameRuleValue::Float(1.5),
+                },
+            ],
+            experiments: vec![resource_pack_stack::Experiment {
+                name: "data_driven_items".to_owned(),
+                enabled: true,
+            }],
+            persona_disabled: protocol >= V1_19_20,
+            custom_skins_disabled: protocol >= V1_19_20,
+            emote_chat_muted: protocol >= V1_20_30,
+            education_uri: EducationUri {
+                button_name: "b".to_owned(),
+                link_uri: "l".to_owned(),
+            },
+            force_experimental_gameplay: Some(true),
+            chat_restriction_level: if protocol >= V1_19_20 { 1 } else { 0 },
+            player_interactions_disabled: protocol >= V1_19_20,
+            ..LevelSettings::default()
+        },
+        level_id: "level".to_owned(),
+        template_content_identity: "template".to_owned(),
+        movement: MovementSettings {
+            authority: MovementAuthority::ServerWithRewind,
+            rewind_history_size: 40,
+            server_authoritative_block_breaking: true,
+        },
+        current_tick: 100,
+        enchantment_seed: -7,
+        block_properties: vec![BlockProperty {
+            name: "custom:block".to_owned(),
+            properties: Tag::Compound(properties.clone()),
+        }],
+        items: vec![ItemEntry {
+            name: "minecraft:stone".to_owned(),
+            runtime_id: 1,
+            component_based: false,
+        }],
+        server_authoritative_inventory: true,
+        engine: "1.20.60".to_owned(),
+        property_data: if protocol >= V1_18_30 {
+            Tag::Compound(properties)
+        } else {
+            Tag::Compound(Compound::new())
+        },
+        block_registry_checksum: 0xdead_beef,
+        world_template_id: Uuid(1 << 100 | 7),
+        client_side_generation: protocol >= V1_19_0,
+        block_network_ids_are_hashes: protocol >= V1_19_80,
+        server_authoritative_sound: protocol >= V1_20_0,
+        ..StartGame::default()
+    }
+}
+
+#[test]
+fn packets_round_trip() {
+    use crate::{
+        nbt::{Compound, Tag},
+        protocol::{
+            compression::Compression,
+            types::{
+                item::ItemInstance,
+                position::{BlockPos, ChunkPos},
+            },
+            version,
+        },
+    };
+
+    for (protocol, game_version) in version::SUPPORTED.iter().copied() {
+        round_trip(
+            client_cache_status::ClientCacheStatus { enabled: true },
+            protocol,
+        );
+        round_trip(
+            client_to_server_handshake::Client2ServerHandshake {},
+            protocol,
+        );
+        round_trip(
+            disconnect::Disconnect {
+                // not on the wire before 1.20.40
+                reason: if protocol >= version::V1_20_40 {
+                    disconnect::DisconnectReason::Kicked
+                } else {
+                    disconnect::DisconnectReason::Unknown
+                },
+                hide_kick_message: false,
+                kick_message: "bye".to_owned(),
+            },
+            protocol,
+        );
+        round_trip(
+            login_packet::LoginPacket {
+                protocol_version: protocol,
+                chain: r#"{"chain":[]}"#.to_owned(),
+                player_data: "a.b.c".to_owned(),
+            },
+            protocol,
+        );
+        round_trip(
+            network_settings::NetworkSettings {
+                compression_threshold: 256,
+                compression: Compression::Snappy,
+                client_throttle: true,
+                client_throttle_threshold: 10,
+                client_throttle_scalar: 0.5,
+            },
+            protocol,
+        );
+        for status in [
+            play_status::PlayStatus::LoginSuccess,
+            play_status::PlayStatus::FailedClient,
+            play_status::PlayStatus::FailedServer,
+            play_status::PlayStatus::PlayerSpawn,
+            play_status::PlayStatus::FailedInvalidTenant,
+            play_status::PlayStatus::FailedVanillaEdu,
+            play_status::PlayStatus::FailedEduVanilla,
+            play_status::PlayStatus::FailedServerFull,
+        ] {
+            round_trip(status, protocol);
+        }
+        round_trip(
+            request_network_settings::RequestNetworkSettings {
+                protocol_version: protocol,
+            },
+            protocol,
+        );
+        round_trip(
+            server_to_client_handshake::Server2ClientHandshake {
+                salt: "a.b.c".to_owned(),
+            },
+            protocol,
+        );
+        round_trip(
+            resource_pack_client_response::ResourcePackClientResponse {
+                status: resource_pack_client_response::ResponseStatus::SendPacks,
+                pack_ids: vec!["uuid_1.0.0".to_owned()],
+            },
+            protocol,
+        );
+        round_trip(
+            resource_pack_data_info::ResourcePackDataInfo {
+                pack_id: "uuid_1.0.0".to_owned(),
+                max_chunk_size: 1024,
+                chunk_count: 2,
+                size: 2000,
+                hash: vec![0xab; 32],
+                premium: false,
+                pack_type: resource_pack_data_info::PackType::Resources,
+            },
+            protocol,
+        );
+        round_trip(
+            resource_pack_chunk_request::ResourcePackChunkRequest {
+                pack_id: "uuid_1.0.0".to_owned(),
+                chunk_index: 1,
+            },
+            protocol,
+        );
+        round_trip(
+            resource_pack_chunk_data::ResourcePackChunkData {
+                pack_id: "uuid_1.0.0".to_owned(),
+                chunk_index: 1,
+                offset: 1024,
+                data: vec![1, 2, 3],
+            },
+            protocol,
+        );
+        let pack = resource_pack_stack::StackPack {
+            uuid: "uuid".to_owned(),
+            version: "1.0.0".to_owned(),
+            sub_pack_name: "".to_owned(),
+        };
+        round_trip(
+            resource_pack_stack::ResourcePackStack {
+                must_accept: true,
+                behavior: vec![pack.clone()],
+                texture: vec![pack],
+                game_version: game_version.to_owned(),
+                experiments: vec![resource_pack_stack::Experiment {
+                    name: "data_driven_items".to_owned(),
+                    enabled: true,
+                }],
+                experiments_previously_toggled: true,
+            },
+            protocol,
+        );
+        let info = |rtx_enabled| resource_packs_info::PackInfo {
+            uuid: "uuid".to_owned(),
+            version: "1.0.0".to_owned(),
+            size: 1024,
+            content_key: "".to_owned(),
+            sub_pack_name: "".to_owned(),
+            content_identity: "".to_owned(),
+            has_scripts: false,
+            rtx_enabled,
+        };
+        round_trip(
+            resource_packs_info::ResourcePacksInfo {
+                force_accept: true,
+                has_script: false,
+                force_server_packs: true,
+                behavior: vec![info(false)],
+                texture: vec![info(true)],
+                // not on the wire before 1.20.30
+                urls: if protocol >= version::V1_20_30 {
+                    vec![resource_packs_info::PackUrl {
+                        uuid_version: "uuid_1.0.0".to_owned(),
+                        url: "https://cdn.example.com/pack.zip".to_owned(),
+                    }]
+                } else {
+                    vec![]
+                },
+            },
+            protocol,
+        );
+        round_trip(
+            set_local_player_as_initialized::SetLocalPlayerAsInitialized { runtime_id: 1 },
+            protocol,
+        );
+        round_trip(start_game(protocol), protocol);
+        let mut compound = Compound::new();
+        compound.insert("plains".to_owned(), Tag::Compound(Compound::new()));
+        round_trip(
+            biome_definition_list::BiomeDefinitionList {
+                definitions: Tag::Compound(compound.clone()),
+            },
+            protocol,
+        );
+        round_trip(
+            available_actor_identifiers::AvailableActorIdentifiers {
+                identifiers: Tag::Compound(compound),
+            },
+            protocol,
+        );
+        round_trip(
+            creative_content::CreativeContent {
+                items: vec![
+                    creative_content::CreativeItem {
+                        network_id: 1,
+                        item: ItemInstance {
+                            network_id: 5,
+                            count: 1,
+                            metadata: 2,
+                            block_runtime_id: 3,
+                            extra_data: vec![0, 0],
+                        },
+                    },
+                    creative_content::CreativeItem {
+                        network_id: 2,
+                        item: ItemInstance::default(),
+                    },
+                ],
+            },
+            protocol,
+        );
+        round_trip(
+            request_chunk_radius::RequestChunkRadius {
+                chunk_radius: 8,
+                // not on the wire before 1.19.80
+                max_chunk_radius: if protocol >= version::V1_19_80 { 12 } else { 0 },
+            },
+            protocol,
+        );
+        round_trip(
+            chunk_radius_updated::ChunkRadiusUpdated { chunk_radius: 8 },
+            protocol,
+        );
+        let chunk = crate::world::chunk::Chunk::new(ChunkPos { x: 1, z: -1 }, 0..8, 1, 0);
+        let mut level_chunk = level_chunk::LevelChunk::new(&chunk, 0).unwrap();
+        round_trip(level_chunk.clone(), protocol);
+        level_chunk.sub_chunk_count = level_chunk::SubChunkCount::Limited(3);
+        level_chunk.blob_hashes = Some(vec![1, u64::MAX]);
+        // not on the wire before 1.20.60
+        if protocol >= version::V1_20_60 {
+            level_chunk.dimension = 1;
+        }
+        round_trip(level_chunk.clone(), protocol);
+        level_chunk.sub_chunk_count = level_chunk::SubChunkCount::Limitless;
+        round_trip(level_chunk, protocol);
+        round_trip(
+            network_chunk_publisher_update::NetworkChunkPublisherUpdate {
+                position: BlockPos { x: -1, y: 64, z: 1 },
+                radius: 128,
+                // not on the wire before 1.19.20
+                saved_chunks: if protocol >= version::V1_19_20 {
+                    vec![ChunkPos { x: -1, z: 2 }]
+                } else {
+                    vec![]
+                },
+            },
+            protocol,
+        );
+        round_trip(
+            sub_client_login::SubClientLogin {
+                chain: r#"{"chain":[]}"#.to_owned(),
+                player_data: "a.b.c".to_owned(),
+            },
+            protocol,
+        );
+    }
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x1ff)]
+struct Derived {
+    #[packet(big_endian)]
+    a: u32,
+    #[packet(varint)]
+    b: i32,
+    #[packet(prefix = "u16")]
+    c: Vec<u8>,
+    #[packet(prefix = "u32")]
+    d: String,
+    #[packet(since = V1_20_30)]
+    e: Option<u8>,
+    #[packet(since = V1_18_0, before = V1_20_30)]
+    f: u8,
+}
+
+#[test]
+fn derived() {
+    use crate::protocol::version;
+
+    let packet = Derived {
+        a: 1,
+        b: -1,
+        c: vec![2],
+        d: "d".to_owned(),
+        e: Some(3),
+        f: 0,
+    };
+    let buf = packet.write(version::V1_20_30).unwrap();
+    assert_eq!(buf, [0, 0, 0, 1, 1, 1, 0, 2, 1, 0, 0, 0, b'd', 1, 3]);
+    round_trip(packet.clone(), version::V1_20_30);
+    let old = Derived {
+        e: None,
+        f: 4,
+        ..packet
+    };
+    let buf = old.write(version::V1_20_10).unwrap();
+    assert_eq!(buf, [0, 0, 0, 1, 1, 1, 0, 2, 1, 0, 0, 0, b'd', 4]);
+    round_trip(old, version::V1_20_10);
+}
+
+#[test]
+fn header() {
+    let header = Header {
+        id: 0x135,
+        sender: 3,
+        target: 1,
+    };
+    let buf = header.write().unwrap();
+    assert_eq!(Header::read(&buf).unwrap(), (header, &[][..]));
+    // IDs from 0x80 take two bytes
+    assert_eq!(Header::new(0xc1).write().unwrap(), vec![0xc1, 0x01]);
+}
+
+#[test]
+fn malformed_packets() {
+    use crate::protocol::version;
+
+    let login = encode(
+        login_packet::LoginPacket {
+            protocol_version: version::LATEST,
+            chain: "{}".to_owned(),
+            player_data: "a.b.c".to_owned(),
+        },
+        version::LATEST,
+    )
+    .unwrap();
+    // every truncation fails cleanly
+    for end in 0..login.len() {
+        assert!(decode::<login_packet::LoginPacket>(&login[..end], version::LATEST).is_err());
+    }
+    let trailing = [&login[..], &[0]].concat();
+    assert!(matches!(
+        decode::<login_packet::LoginPacket>(&trailing, version::LATEST),
+        Err(DecodeError::TrailingBytes(1))
+    ));
+    // a string length pointing far past the end
+    let mut bad = login.clone();
+    let chain_length = bad.len() - "{}".len() - 4 - "a.b.c".len() - 4 - 4;
+    bad[chain_length..chain_length + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(decode::<login_packet::LoginPacket>(&bad, version::LATEST).is_err());
 }
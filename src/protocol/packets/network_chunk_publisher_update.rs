@@ -0,0 +1,15 @@
+use crate::protocol::types::position::{BlockPos, ChunkPos};
+
+use super::Packet;
+
+/// Tells the client which chunks the server is sending it.
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x79)]
+pub struct NetworkChunkPublisherUpdate {
+    pub position: BlockPos,
+    /// In blocks.
+    #[packet(varint)]
+    pub radius: u32,
+    #[packet(since = V1_19_20, prefix = "u32")]
+    pub saved_chunks: Vec<ChunkPos>,
+}
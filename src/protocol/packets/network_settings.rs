@@ -0,0 +1,14 @@
+use crate::protocol::compression::Compression;
+
+use super::Packet;
+
+#[derive(Clone, Debug, PartialEq, Packet)]
+#[packet(id = 0x8f)]
+pub struct NetworkSettings {
+    /// Batches smaller than this many bytes are sent uncompressed.
+    pub compression_threshold: u16,
+    pub compression: Compression,
+    pub client_throttle: bool,
+    pub client_throttle_threshold: u8,
+    pub client_throttle_scalar: f32,
+}
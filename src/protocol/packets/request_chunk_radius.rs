@@ -0,0 +1,11 @@
+use super::Packet;
+
+/// The client's view distance in chunks, answered with `ChunkRadiusUpdated`.
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x45)]
+pub struct RequestChunkRadius {
+    #[packet(varint)]
+    pub chunk_radius: i32,
+    #[packet(since = V1_19_80)]
+    pub max_chunk_radius: u8,
+}
@@ -0,0 +1,9 @@
+use super::Packet;
+
+/// First packet of clients from 1.19.30 on, sent before compression is enabled.
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0xc1)]
+pub struct RequestNetworkSettings {
+    #[packet(big_endian)]
+    pub protocol_version: u32,
+}
@@ -0,0 +1,12 @@
+use super::Packet;
+
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x53)]
+pub struct ResourcePackChunkData {
+    /// `uuid_version` of the pack.
+    pub pack_id: String,
+    pub chunk_index: u32,
+    /// Where the chunk starts in the archive.
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
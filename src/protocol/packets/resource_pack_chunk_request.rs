@@ -0,0 +1,9 @@
+use super::Packet;
+
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x54)]
+pub struct ResourcePackChunkRequest {
+    /// `uuid_version` of the pack.
+    pub pack_id: String,
+    pub chunk_index: u32,
+}
@@ -0,0 +1,45 @@
+use crate::{
+    encoding::{Decode, Encode},
+    reader::{self, DecodeError, Reader},
+    writer::Writer,
+};
+
+use super::Packet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResponseStatus {
+    None,
+    Refused,
+    SendPacks,
+    HaveAllPacks,
+    Completed,
+}
+
+impl Encode for ResponseStatus {
+    fn encode(&self, cursor: &mut Writer) -> std::io::Result<()> {
+        (*self as u8).encode(cursor)
+    }
+}
+
+impl Decode for ResponseStatus {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        match u8::decode(cursor)? {
+            0 => Ok(ResponseStatus::None),
+            1 => Ok(ResponseStatus::Refused),
+            2 => Ok(ResponseStatus::SendPacks),
+            3 => Ok(ResponseStatus::HaveAllPacks),
+            4 => Ok(ResponseStatus::Completed),
+            _ => Err(DecodeError::InvalidValue(
+                "Unknown response status".to_owned(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x8)]
+pub struct ResourcePackClientResponse {
+    pub status: ResponseStatus,
+    #[packet(prefix = "u16")]
+    pub pack_ids: Vec<String>,
+}
@@ -0,0 +1,58 @@
+use crate::{
+    encoding::{Decode, Encode},
+    reader::{self, DecodeError, Reader},
+    writer::Writer,
+};
+
+use super::Packet;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackType {
+    Invalid,
+    Addon,
+    Cached,
+    CopyProtected,
+    Behavior,
+    PersonaPiece,
+    Resources,
+    Skins,
+    WorldTemplate,
+}
+
+impl Encode for PackType {
+    fn encode(&self, cursor: &mut Writer) -> std::io::Result<()> {
+        (*self as u8).encode(cursor)
+    }
+}
+
+impl Decode for PackType {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        match u8::decode(cursor)? {
+            0 => Ok(PackType::Invalid),
+            1 => Ok(PackType::Addon),
+            2 => Ok(PackType::Cached),
+            3 => Ok(PackType::CopyProtected),
+            4 => Ok(PackType::Behavior),
+            5 => Ok(PackType::PersonaPiece),
+            6 => Ok(PackType::Resources),
+            7 => Ok(PackType::Skins),
+            8 => Ok(PackType::WorldTemplate),
+            _ => Err(DecodeError::InvalidValue("Unknown pack type".to_owned())),
+        }
+    }
+}
+
+/// Answers a `SendPacks` response for one pack; the client then asks for every chunk.
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x52)]
+pub struct ResourcePackDataInfo {
+    /// `uuid_version` of the pack.
+    pub pack_id: String,
+    pub max_chunk_size: u32,
+    pub chunk_count: u32,
+    pub size: u64,
+    /// SHA-256 of the whole archive.
+    pub hash: Vec<u8>,
+    pub premium: bool,
+    pub pack_type: PackType,
+}
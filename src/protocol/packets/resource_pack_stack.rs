@@ -0,0 +1,28 @@
+use crate::encoding::{Decode, Encode};
+
+use super::Packet;
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct StackPack {
+    pub uuid: String,
+    pub version: String,
+    pub sub_pack_name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct Experiment {
+    pub name: String,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x7)]
+pub struct ResourcePackStack {
+    pub must_accept: bool,
+    pub behavior: Vec<StackPack>,
+    pub texture: Vec<StackPack>,
+    pub game_version: String,
+    #[packet(prefix = "u32")]
+    pub experiments: Vec<Experiment>,
+    pub experiments_previously_toggled: bool,
+}
@@ -1,42 +1,115 @@
-use crate::{writer::Writer, reader::{Reader, Endian}};
+use crate::{
+    encoding::{Decode, Encode},
+    protocol::version::V1_20_30,
+    reader::{self, Reader},
+    writer::Writer,
+};
 
 use super::Packet;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackInfo {
+    pub uuid: String,
+    pub version: String,
+    /// Size of the pack archive in bytes.
+    pub size: u64,
+    /// Key the pack is encrypted with, empty for plain packs.
+    pub content_key: String,
+    pub sub_pack_name: String,
+    pub content_identity: String,
+    pub has_scripts: bool,
+    /// Only sent for texture packs.
+    pub rtx_enabled: bool,
+}
+
+/// Where the client may download a pack instead of asking for chunks, from 1.20.30 on.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct PackUrl {
+    /// `uuid_version` of the pack.
+    pub uuid_version: String,
+    pub url: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ResourcePacksInfo {
-    pub force_accept : bool,
-    pub has_script : bool,
-    pub force_server_packs : bool,
-    pub behavior : Vec<String>,
-    pub texture : Vec<String>
+    pub force_accept: bool,
+    pub has_script: bool,
+    pub force_server_packs: bool,
+    pub behavior: Vec<PackInfo>,
+    pub texture: Vec<PackInfo>,
+    pub urls: Vec<PackUrl>,
+}
+
+// a u16 LE count, and `rtx_enabled` only follows texture packs
+fn read_packs(cursor: &mut Reader, texture: bool) -> reader::Result<Vec<PackInfo>> {
+    let count = u16::decode(cursor)?;
+    let mut packs = vec![];
+    for _ in 0..count {
+        packs.push(PackInfo {
+            uuid: String::decode(cursor)?,
+            version: String::decode(cursor)?,
+            size: u64::decode(cursor)?,
+            content_key: String::decode(cursor)?,
+            sub_pack_name: String::decode(cursor)?,
+            content_identity: String::decode(cursor)?,
+            has_scripts: bool::decode(cursor)?,
+            rtx_enabled: texture && bool::decode(cursor)?,
+        });
+    }
+    Ok(packs)
+}
+
+fn write_packs(cursor: &mut Writer, packs: &[PackInfo], texture: bool) -> std::io::Result<()> {
+    (packs.len() as u16).encode(cursor)?;
+    for pack in packs {
+        pack.uuid.encode(cursor)?;
+        pack.version.encode(cursor)?;
+        pack.size.encode(cursor)?;
+        pack.content_key.encode(cursor)?;
+        pack.sub_pack_name.encode(cursor)?;
+        pack.content_identity.encode(cursor)?;
+        pack.has_scripts.encode(cursor)?;
+        if texture {
+            pack.rtx_enabled.encode(cursor)?;
+        }
+    }
+    Ok(())
 }
 
 impl Packet for ResourcePacksInfo {
-    const ID: u8 = 0x6;
-
-    fn read(buf: &[u8]) -> std::io::Result<Self> {
-        let mut cursor = Reader::new(buf);
-        Ok(Self { 
-            force_accept : cursor.read_u8()? != 0,
-            has_script : cursor.read_u8()? != 0,
-            force_server_packs : cursor.read_u8()? != 0,
-            behavior: vec![],
-            texture: vec![],
-         })
+    const ID: u16 = 0x6;
+
+    fn read(cursor: &mut Reader, protocol: u32) -> reader::Result<Self> {
+        let force_accept = bool::decode(cursor)?;
+        let has_script = bool::decode(cursor)?;
+        let force_server_packs = bool::decode(cursor)?;
+        let behavior = read_packs(cursor, false)?;
+        let texture = read_packs(cursor, true)?;
+        let urls = if protocol >= V1_20_30 {
+            Vec::decode(cursor)?
+        } else {
+            vec![]
+        };
+        Ok(Self {
+            force_accept,
+            has_script,
+            force_server_packs,
+            behavior,
+            texture,
+            urls,
+        })
     }
 
-    fn write(&self) -> std::io::Result<Vec<u8>> {
+    fn write(&self, protocol: u32) -> std::io::Result<Vec<u8>> {
         let mut cursor = Writer::new(vec![]);
-        cursor.write_u8(self.force_accept as u8)?;
-        cursor.write_u8(self.has_script as u8)?;
-        cursor.write_u8(self.force_server_packs as u8)?;
-        cursor.write_varu32(4)?;
-        cursor.write_u16(self.behavior.len() as u16,Endian::Big)?;
-        //write behaviors
-        cursor.write_u16(self.texture.len() as u16,Endian::Big)?;
-        //write textures
+        self.force_accept.encode(&mut cursor)?;
+        self.has_script.encode(&mut cursor)?;
+        self.force_server_packs.encode(&mut cursor)?;
+        write_packs(&mut cursor, &self.behavior, false)?;
+        write_packs(&mut cursor, &self.texture, true)?;
+        if protocol >= V1_20_30 {
+            self.urls.encode(&mut cursor)?;
+        }
         Ok(cursor.get_raw_payload())
     }
 }
-
-
@@ -0,0 +1,8 @@
+use super::Packet;
+
+#[derive(Clone, Debug, PartialEq, Eq, Packet)]
+#[packet(id = 0x71)]
+pub struct SetLocalPlayerAsInitialized {
+    #[packet(varint)]
+    pub runtime_id: u64,
+}
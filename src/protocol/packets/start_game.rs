@@ -0,0 +1,612 @@
+use std::io::Result;
+
+use crate::{
+    encoding::{Decode, Encode, VarI32, VarI64, VarU32, VarU64, VecU32},
+    nbt::{Compound, Tag},
+    protocol::{
+        types::{
+            position::{BlockPos, Vec3},
+            uuid::Uuid,
+        },
+        version::{V1_18_30, V1_19_0, V1_19_10, V1_19_20, V1_19_70, V1_19_80, V1_20_0, V1_20_30},
+    },
+    reader::{self, DecodeError, Reader},
+    writer::Writer,
+};
+
+use super::{resource_pack_stack::Experiment, Packet};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameRuleValue {
+    Bool(bool),
+    Int(u32),
+    Float(f32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRule {
+    pub name: String,
+    pub editable: bool,
+    pub value: GameRuleValue,
+}
+
+/// The value type as a varuint32, 1 bool, 2 int and 3 float, then the value.
+impl Encode for GameRule {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        self.name.encode(cursor)?;
+        self.editable.encode(cursor)?;
+        match self.value {
+            GameRuleValue::Bool(p) => {
+                VarU32(1).encode(cursor)?;
+                p.encode(cursor)
+            }
+            GameRuleValue::Int(p) => {
+                VarU32(2).encode(cursor)?;
+                VarU32(p).encode(cursor)
+            }
+            GameRuleValue::Float(p) => {
+                VarU32(3).encode(cursor)?;
+                p.encode(cursor)
+            }
+        }
+    }
+}
+
+impl Decode for GameRule {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        let name = String::decode(cursor)?;
+        let editable = bool::decode(cursor)?;
+        let value = match VarU32::decode(cursor)?.0 {
+            1 => GameRuleValue::Bool(bool::decode(cursor)?),
+            2 => GameRuleValue::Int(VarU32::decode(cursor)?.0),
+            3 => GameRuleValue::Float(f32::decode(cursor)?),
+            _ => {
+                return Err(DecodeError::InvalidValue(
+                    "Unknown game rule type".to_owned(),
+                ))
+            }
+        };
+        Ok(Self {
+            name,
+            editable,
+            value,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct EducationUri {
+    pub button_name: String,
+    pub link_uri: String,
+}
+
+/// Who decides where the player is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MovementAuthority {
+    #[default]
+    Client,
+    Server,
+    /// Server authoritative, correcting the client by rewinding its inputs.
+    ServerWithRewind,
+}
+
+/// As a zigzag varint32.
+impl Encode for MovementAuthority {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        let authority = match self {
+            MovementAuthority::Client => 0,
+            MovementAuthority::Server => 1,
+            MovementAuthority::ServerWithRewind => 2,
+        };
+        VarI32(authority).encode(cursor)
+    }
+}
+
+impl Decode for MovementAuthority {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        match VarI32::decode(cursor)?.0 {
+            0 => Ok(MovementAuthority::Client),
+            1 => Ok(MovementAuthority::Server),
+            2 => Ok(MovementAuthority::ServerWithRewind),
+            _ => Err(DecodeError::InvalidValue(
+                "Unknown movement authority".to_owned(),
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct MovementSettings {
+    pub authority: MovementAuthority,
+    /// Ticks of inputs kept for `ServerWithRewind`.
+    #[packet(varint)]
+    pub rewind_history_size: i32,
+    pub server_authoritative_block_breaking: bool,
+}
+
+/// Properties of a custom block, sent so the client can build its block palette.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub struct BlockProperty {
+    pub name: String,
+    pub properties: Tag,
+}
+
+/// An entry of the item table, mapping item names to the runtime IDs used in item stacks.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct ItemEntry {
+    pub name: String,
+    pub runtime_id: i16,
+    pub component_based: bool,
+}
+
+/// The world part of `StartGame`. Fields not on the wire for a protocol read as their default.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelSettings {
+    /// A zigzag varint32 before 1.18.30.
+    pub seed: i64,
+    pub spawn_biome_type: i16,
+    pub custom_biome_name: String,
+    pub dimension: i32,
+    pub generator: i32,
+    pub game_mode: i32,
+    pub difficulty: i32,
+    pub spawn: BlockPos,
+    pub achievements_disabled: bool,
+    /// A bool from 1.19.10, an int from 1.19.80.
+    pub editor_world_type: i32,
+    /// From 1.19.70.
+    pub created_in_editor: bool,
+    /// From 1.19.70.
+    pub exported_from_editor: bool,
+    /// From 1.20.0.
+    pub day_cycle_lock_time: i32,
+    pub education_offer: i32,
+    pub education_features: bool,
+    pub education_product_id: String,
+    pub rain_level: f32,
+    pub lightning_level: f32,
+    pub platform_locked_content_confirmed: bool,
+    pub multiplayer: bool,
+    pub lan_broadcast: bool,
+    pub xbl_broadcast_mode: i32,
+    pub platform_broadcast_mode: i32,
+    pub commands_enabled: bool,
+    pub texture_packs_required: bool,
+    pub game_rules: Vec<GameRule>,
+    pub experiments: Vec<Experiment>,
+    pub experiments_previously_toggled: bool,
+    pub bonus_chest: bool,
+    pub start_with_map: bool,
+    pub player_permissions: i32,
+    pub server_chunk_tick_radius: i32,
+    pub locked_behavior_pack: bool,
+    pub locked_texture_pack: bool,
+    pub from_locked_world_template: bool,
+    pub msa_gamertags_only: bool,
+    pub from_world_template: bool,
+    pub world_template_settings_locked: bool,
+    pub only_spawn_v1_villagers: bool,
+    /// From 1.19.20.
+    pub persona_disabled: bool,
+    /// From 1.19.20.
+    pub custom_skins_disabled: bool,
+    /// From 1.20.30.
+    pub emote_chat_muted: bool,
+    pub base_game_version: String,
+    pub limited_world_width: i32,
+    pub limited_world_depth: i32,
+    pub new_nether: bool,
+    pub education_uri: EducationUri,
+    pub force_experimental_gameplay: Option<bool>,
+    /// From 1.19.20.
+    pub chat_restriction_level: u8,
+    /// From 1.19.20.
+    pub player_interactions_disabled: bool,
+}
+
+impl Default for LevelSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            spawn_biome_type: 0,
+            custom_biome_name: String::new(),
+            dimension: 0,
+            generator: 1,
+            game_mode: 0,
+            difficulty: 1,
+            spawn: BlockPos { x: 0, y: 64, z: 0 },
+            achievements_disabled: true,
+            editor_world_type: 0,
+            created_in_editor: false,
+            exported_from_editor: false,
+            day_cycle_lock_time: 0,
+            education_offer: 0,
+            education_features: false,
+            education_product_id: String::new(),
+            rain_level: 0.0,
+            lightning_level: 0.0,
+            platform_locked_content_confirmed: false,
+            multiplayer: true,
+            lan_broadcast: true,
+            xbl_broadcast_mode: 3,
+            platform_broadcast_mode: 3,
+            commands_enabled: true,
+            texture_packs_required: false,
+            game_rules: vec![],
+            experiments: vec![],
+            experiments_previously_toggled: false,
+            bonus_chest: false,
+            start_with_map: false,
+            player_permissions: 1,
+            server_chunk_tick_radius: 4,
+            locked_behavior_pack: false,
+            locked_texture_pack: false,
+            from_locked_world_template: false,
+            msa_gamertags_only: false,
+            from_world_template: false,
+            world_template_settings_locked: false,
+            only_spawn_v1_villagers: false,
+            persona_disabled: false,
+            custom_skins_disabled: false,
+            emote_chat_muted: false,
+            base_game_version: "*".to_owned(),
+            limited_world_width: 0,
+            limited_world_depth: 0,
+            new_nether: true,
+            education_uri: EducationUri::default(),
+            force_experimental_gameplay: None,
+            chat_restriction_level: 0,
+            player_interactions_disabled: false,
+        }
+    }
+}
+
+impl LevelSettings {
+    fn read(cursor: &mut Reader, protocol: u32) -> reader::Result<Self> {
+        Ok(Self {
+            seed: if protocol >= V1_18_30 {
+                i64::decode(cursor)?
+            } else {
+                VarI32::decode(cursor)?.0 as i64
+            },
+            spawn_biome_type: i16::decode(cursor)?,
+            custom_biome_name: String::decode(cursor)?,
+            dimension: VarI32::decode(cursor)?.0,
+            generator: VarI32::decode(cursor)?.0,
+            game_mode: VarI32::decode(cursor)?.0,
+            difficulty: VarI32::decode(cursor)?.0,
+            // the spawn's y is a varuint32
+            spawn: BlockPos {
+                x: VarI32::decode(cursor)?.0,
+                y: VarU32::decode(cursor)?.0 as i32,
+                z: VarI32::decode(cursor)?.0,
+            },
+            achievements_disabled: bool::decode(cursor)?,
+            editor_world_type: if protocol >= V1_19_80 {
+                VarI32::decode(cursor)?.0
+            } else if protocol >= V1_19_10 {
+                bool::decode(cursor)? as i32
+            } else {
+                0
+            },
+            created_in_editor: protocol >= V1_19_70 && bool::decode(cursor)?,
+            exported_from_editor: protocol >= V1_19_70 && bool::decode(cursor)?,
+            day_cycle_lock_time: if protocol >= V1_20_0 {
+                VarI32::decode(cursor)?.0
+            } else {
+                0
+            },
+            education_offer: VarI32::decode(cursor)?.0,
+            education_features: bool::decode(cursor)?,
+            education_product_id: String::decode(cursor)?,
+            rain_level: f32::decode(cursor)?,
+            lightning_level: f32::decode(cursor)?,
+            platform_locked_content_confirmed: bool::decode(cursor)?,
+            multiplayer: bool::decode(cursor)?,
+            lan_broadcast: bool::decode(cursor)?,
+            xbl_broadcast_mode: VarI32::decode(cursor)?.0,
+            platform_broadcast_mode: VarI32::decode(cursor)?.0,
+            commands_enabled: bool::decode(cursor)?,
+            texture_packs_required: bool::decode(cursor)?,
+            game_rules: Vec::decode(cursor)?,
+            experiments: VecU32::decode(cursor)?.0,
+            experiments_previously_toggled: bool::decode(cursor)?,
+            bonus_chest: bool::decode(cursor)?,
+            start_with_map: bool::decode(cursor)?,
+            player_permissions: VarI32::decode(cursor)?.0,
+            server_chunk_tick_radius: i32::decode(cursor)?,
+            locked_behavior_pack: bool::decode(cursor)?,
+            locked_texture_pack: bool::decode(cursor)?,
+            from_locked_world_template: bool::decode(cursor)?,
+            msa_gamertags_only: bool::decode(cursor)?,
+            from_world_template: bool::decode(cursor)?,
+            world_template_settings_locked: bool::decode(cursor)?,
+            only_spawn_v1_villagers: bool::decode(cursor)?,
+            persona_disabled: protocol >= V1_19_20 && bool::decode(cursor)?,
+            custom_skins_disabled: protocol >= V1_19_20 && bool::decode(cursor)?,
+            emote_chat_muted: protocol >= V1_20_30 && bool::decode(cursor)?,
+            base_game_version: String::decode(cursor)?,
+            limited_world_width: i32::decode(cursor)?,
+            limited_world_depth: i32::decode(cursor)?,
+            new_nether: bool::decode(cursor)?,
+            education_uri: EducationUri::decode(cursor)?,
+            force_experimental_gameplay: Option::decode(cursor)?,
+            chat_restriction_level: if protocol >= V1_19_20 {
+                u8::decode(cursor)?
+            } else {
+                0
+            },
+            player_interactions_disabled: protocol >= V1_19_20 && bool::decode(cursor)?,
+        })
+    }
+
+    fn write(&self, cursor: &mut Writer, protocol: u32) -> Result<()> {
+        if protocol >= V1_18_30 {
+            self.seed.encode(cursor)?;
+        } else {
+            VarI32(self.seed as i32).encode(cursor)?;
+        }
+        self.spawn_biome_type.encode(cursor)?;
+        self.custom_biome_name.encode(cursor)?;
+        VarI32(self.dimension).encode(cursor)?;
+        VarI32(self.generator).encode(cursor)?;
+        VarI32(self.game_mode).encode(cursor)?;
+        VarI32(self.difficulty).encode(cursor)?;
+        VarI32(self.spawn.x).encode(cursor)?;
+        VarU32(self.spawn.y as u32).encode(cursor)?;
+        VarI32(self.spawn.z).encode(cursor)?;
+        self.achievements_disabled.encode(cursor)?;
+        if protocol >= V1_19_80 {
+            VarI32(self.editor_world_type).encode(cursor)?;
+        } else if protocol >= V1_19_10 {
+            (self.editor_world_type != 0).encode(cursor)?;
+        }
+        if protocol >= V1_19_70 {
+            self.created_in_editor.encode(cursor)?;
+            self.exported_from_editor.encode(cursor)?;
+        }
+        if protocol >= V1_20_0 {
+            VarI32(self.day_cycle_lock_time).encode(cursor)?;
+        }
+        VarI32(self.education_offer).encode(cursor)?;
+        self.education_features.encode(cursor)?;
+        self.education_product_id.encode(cursor)?;
+        self.rain_level.encode(cursor)?;
+        self.lightning_level.encode(cursor)?;
+        self.platform_locked_content_confirmed.encode(cursor)?;
+        self.multiplayer.encode(cursor)?;
+        self.lan_broadcast.encode(cursor)?;
+        VarI32(self.xbl_broadcast_mode).encode(cursor)?;
+        VarI32(self.platform_broadcast_mode).encode(cursor)?;
+        self.commands_enabled.encode(cursor)?;
+        self.texture_packs_required.encode(cursor)?;
+        self.game_rules.encode(cursor)?;
+        VecU32(self.experiments.as_slice()).encode(cursor)?;
+        self.experiments_previously_toggled.encode(cursor)?;
+        self.bonus_chest.encode(cursor)?;
+        self.start_with_map.encode(cursor)?;
+        VarI32(self.player_permissions).encode(cursor)?;
+        self.server_chunk_tick_radius.encode(cursor)?;
+        self.locked_behavior_pack.encode(cursor)?;
+        self.locked_texture_pack.encode(cursor)?;
+        self.from_locked_world_template.encode(cursor)?;
+        self.msa_gamertags_only.encode(cursor)?;
+        self.from_world_template.encode(cursor)?;
+        self.world_template_settings_locked.encode(cursor)?;
+        self.only_spawn_v1_villagers.encode(cursor)?;
+        if protocol >= V1_19_20 {
+            self.persona_disabled.encode(cursor)?;
+            self.custom_skins_disabled.encode(cursor)?;
+        }
+        if protocol >= V1_20_30 {
+            self.emote_chat_muted.encode(cursor)?;
+        }
+        self.base_game_version.encode(cursor)?;
+        self.limited_world_width.encode(cursor)?;
+        self.limited_world_depth.encode(cursor)?;
+        self.new_nether.encode(cursor)?;
+        self.education_uri.encode(cursor)?;
+        self.force_experimental_gameplay.encode(cursor)?;
+        if protocol >= V1_19_20 {
+            self.chat_restriction_level.encode(cursor)?;
+            self.player_interactions_disabled.encode(cursor)?;
+        }
+        Ok(())
+    }
+}
+
+/// Puts the player in the world. Sent once resource packs are done, followed by
+/// `BiomeDefinitionList`, `AvailableActorIdentifiers` and `CreativeContent`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StartGame {
+    pub entity_id: i64,
+    pub runtime_id: u64,
+    pub player_game_mode: i32,
+    pub position: Vec3,
+    pub pitch: f32,
+    pub yaw: f32,
+    pub settings: LevelSettings,
+    pub level_id: String,
+    pub world_name: String,
+    pub template_content_identity: String,
+    pub trial: bool,
+    pub movement: MovementSettings,
+    pub current_tick: i64,
+    pub enchantment_seed: i32,
+    pub block_properties: Vec<BlockProperty>,
+    pub items: Vec<ItemEntry>,
+    pub multiplayer_correlation_id: String,
+    pub server_authoritative_inventory: bool,
+    /// Game version of the server.
+    pub engine: String,
+    /// From 1.18.30.
+    pub property_data: Tag,
+    pub block_registry_checksum: u64,
+    pub world_template_id: Uuid,
+    /// From 1.19.0.
+    pub client_side_generation: bool,
+    /// From 1.19.80. Block runtime IDs are FNV-1a hashes of the block states instead of
+    /// palette indices.
+    pub block_network_ids_are_hashes: bool,
+    /// From 1.20.0.
+    pub server_authoritative_sound: bool,
+}
+
+impl Default for StartGame {
+    fn default() -> Self {
+        Self {
+            entity_id: 1,
+            runtime_id: 1,
+            player_game_mode: 0,
+            position: Vec3 {
+                x: 0.5,
+                y: 65.62,
+                z: 0.5,
+            },
+            pitch: 0.0,
+            yaw: 0.0,
+            settings: LevelSettings::default(),
+            level_id: String::new(),
+            world_name: "bers".to_owned(),
+            template_content_identity: String::new(),
+            trial: false,
+            movement: MovementSettings::default(),
+            current_tick: 0,
+            enchantment_seed: 0,
+            block_properties: vec![],
+            items: vec![],
+            multiplayer_correlation_id: String::new(),
+            server_authoritative_inventory: false,
+            engine: String::new(),
+            property_data: Tag::Compound(Compound::new()),
+            block_registry_checksum: 0,
+            world_template_id: Uuid::default(),
+            client_side_generation: false,
+            block_network_ids_are_hashes: false,
+            server_authoritative_sound: false,
+        }
+    }
+}
+
+impl Packet for StartGame {
+    const ID: u16 = 0xb;
+
+    fn read(cursor: &mut Reader, protocol: u32) -> reader::Result<Self> {
+        Ok(Self {
+            entity_id: VarI64::decode(cursor)?.0,
+            runtime_id: VarU64::decode(cursor)?.0,
+            player_game_mode: VarI32::decode(cursor)?.0,
+            position: Vec3::decode(cursor)?,
+            pitch: f32::decode(cursor)?,
+            yaw: f32::decode(cursor)?,
+            settings: LevelSettings::read(cursor, protocol)?,
+            level_id: String::decode(cursor)?,
+            world_name: String::decode(cursor)?,
+            template_content_identity: String::decode(cursor)?,
+            trial: bool::decode(cursor)?,
+            movement: MovementSettings::decode(cursor)?,
+            current_tick: i64::decode(cursor)?,
+            enchantment_seed: VarI32::decode(cursor)?.0,
+            block_properties: Vec::decode(cursor)?,
+            items: Vec::decode(cursor)?,
+            multiplayer_correlation_id: String::decode(cursor)?,
+            server_authoritative_inventory: bool::decode(cursor)?,
+            engine: String::decode(cursor)?,
+            property_data: if protocol >= V1_18_30 {
+                Tag::decode(cursor)?
+            } else {
+                Tag::Compound(Compound::new())
+            },
+            block_registry_checksum: u64::decode(cursor)?,
+            world_template_id: Uuid::decode(cursor)?,
+            client_side_generation: protocol >= V1_19_0 && bool::decode(cursor)?,
+            block_network_ids_are_hashes: protocol >= V1_19_80 && bool::decode(cursor)?,
+            server_authoritative_sound: protocol >= V1_20_0 && bool::decode(cursor)?,
+        })
+    }
+
+    fn write(&self, protocol: u32) -> Result<Vec<u8>> {
+        let mut cursor = Writer::new(vec![]);
+        VarI64(self.entity_id).encode(&mut cursor)?;
+        VarU64(self.runtime_id).encode(&mut cursor)?;
+        VarI32(self.player_game_mode).encode(&mut cursor)?;
+        self.position.encode(&mut cursor)?;
+        self.pitch.encode(&mut cursor)?;
+        self.yaw.encode(&mut cursor)?;
+        self.settings.write(&mut cursor, protocol)?;
+        self.level_id.encode(&mut cursor)?;
+        self.world_name.encode(&mut cursor)?;
+        self.template_content_identity.encode(&mut cursor)?;
+        self.trial.encode(&mut cursor)?;
+        self.movement.encode(&mut cursor)?;
+        self.current_tick.encode(&mut cursor)?;
+        VarI32(self.enchantment_seed).encode(&mut cursor)?;
+        self.block_properties.encode(&mut cursor)?;
+        self.items.encode(&mut cursor)?;
+        self.multiplayer_correlation_id.encode(&mut cursor)?;
+        self.server_authoritative_inventory.encode(&mut cursor)?;
+        self.engine.encode(&mut cursor)?;
+        if protocol >= V1_18_30 {
+            self.property_data.encode(&mut cursor)?;
+        }
+        self.block_registry_checksum.encode(&mut cursor)?;
+        self.world_template_id.encode(&mut cursor)?;
+        if protocol >= V1_19_0 {
+            self.client_side_generation.encode(&mut cursor)?;
+        }
+        if protocol >= V1_19_80 {
+            self.block_network_ids_are_hashes.encode(&mut cursor)?;
+        }
+        if protocol >= V1_20_0 {
+            self.server_authoritative_sound.encode(&mut cursor)?;
+        }
+        Ok(cursor.get_raw_payload())
+    }
+}
+
+#[test]
+fn version_gates() {
+    use super::{decode, encode};
+
+    let start_game = StartGame {
+        client_side_generation: true,
+        block_network_ids_are_hashes: true,
+        server_authoritative_sound: true,
+        ..StartGame::default()
+    };
+    let v1_19_70 = encode(start_game.clone(), V1_19_70).unwrap();
+    let v1_19_80 = encode(start_game.clone(), V1_19_80).unwrap();
+    let v1_20_0 = encode(start_game.clone(), V1_20_0).unwrap();
+
+    // the editor world type is a bool before 1.19.80, a VarI32 after, and the same byte for
+    // 0, so the hashed IDs flag is all 1.19.80 adds
+    assert_eq!(v1_19_80, [&v1_19_70[..], &[1]].concat());
+    // 1.20.0 adds the day cycle lock time in the level settings and the sound flag at the end
+    assert_eq!(v1_20_0.len(), v1_19_80.len() + 2);
+    assert!(v1_20_0.ends_with(&[1, 1, 1]));
+
+    // each side of a gate only reads its own payloads
+    assert!(decode::<StartGame>(&v1_19_70, V1_19_80).is_err());
+    assert!(matches!(
+        decode::<StartGame>(&v1_19_80, V1_19_70),
+        Err(DecodeError::TrailingBytes(1))
+    ));
+    assert!(decode::<StartGame>(&v1_19_80, V1_20_0).is_err());
+    let old = decode::<StartGame>(&v1_19_70, V1_19_70).unwrap();
+    assert!(!old.block_network_ids_are_hashes && !old.server_authoritative_sound);
+    assert_eq!(decode::<StartGame>(&v1_20_0, V1_20_0).unwrap(), start_game);
+
+    // editor world types other than none only survive from 1.19.80
+    let mut editor = start_game.clone();
+    editor.settings.editor_world_type = 2;
+    let decoded = decode::<StartGame>(&encode(editor.clone(), V1_19_70).unwrap(), V1_19_70);
+    assert_eq!(decoded.unwrap().settings.editor_world_type, 1);
+    let decoded = decode::<StartGame>(&encode(editor.clone(), V1_19_80).unwrap(), V1_19_80);
+    assert_eq!(
+        decoded.unwrap(),
+        StartGame {
+            server_authoritative_sound: false,
+            ..editor
+        }
+    );
+}
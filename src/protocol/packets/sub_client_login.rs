@@ -0,0 +1,32 @@
+use crate::{
+    reader::{self, Reader},
+    writer::Writer,
+};
+
+use super::{
+    login_packet::{read_request, write_request},
+    Packet,
+};
+
+/// Login of a split-screen player joining an existing connection. The header's `sender`
+/// names the sub-client.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubClientLogin {
+    pub chain: String,
+    pub player_data: String,
+}
+
+impl Packet for SubClientLogin {
+    const ID: u16 = 0x5e;
+
+    fn read(cursor: &mut Reader, _protocol: u32) -> reader::Result<Self> {
+        let (chain, player_data) = read_request(cursor)?;
+        Ok(Self { chain, player_data })
+    }
+
+    fn write(&self, _protocol: u32) -> std::io::Result<Vec<u8>> {
+        let mut cursor = Writer::new(vec![]);
+        write_request(&mut cursor, &self.chain, &self.player_data)?;
+        Ok(cursor.get_raw_payload())
+    }
+}
@@ -0,0 +1,48 @@
+use std::io::Result;
+
+use crate::{
+    encoding::{Decode, Encode, VarI32, VarU32},
+    reader::{self, Reader},
+    writer::Writer,
+};
+
+/// An item stack without a stack network ID, as in creative content. Air is just a 0
+/// network ID.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ItemInstance {
+    pub network_id: i32,
+    pub count: u16,
+    pub metadata: u32,
+    pub block_runtime_id: i32,
+    /// NBT and the can-place-on and can-destroy lists, kept as the raw blob.
+    pub extra_data: Vec<u8>,
+}
+
+impl Encode for ItemInstance {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        VarI32(self.network_id).encode(cursor)?;
+        if self.network_id == 0 {
+            return Ok(());
+        }
+        self.count.encode(cursor)?;
+        VarU32(self.metadata).encode(cursor)?;
+        VarI32(self.block_runtime_id).encode(cursor)?;
+        self.extra_data.encode(cursor)
+    }
+}
+
+impl Decode for ItemInstance {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        let network_id = VarI32::decode(cursor)?.0;
+        if network_id == 0 {
+            return Ok(Self::default());
+        }
+        Ok(Self {
+            network_id,
+            count: u16::decode(cursor)?,
+            metadata: VarU32::decode(cursor)?.0,
+            block_runtime_id: VarI32::decode(cursor)?.0,
+            extra_data: Vec::decode(cursor)?,
+        })
+    }
+}
@@ -1 +1,4 @@
+pub mod item;
 pub mod player_data;
+pub mod position;
+pub mod uuid;
@@ -174,19 +174,16 @@ impl ExtraData {
                 return None;
             }
         };
-        let title_id_raw = match value.get("titleId") {
-            Some(p) => p,
-            None => {
-                eprintln!("titleId don't exists");
-                return None;
-            }
-        };
-        let title_id = match title_id_raw.as_str() {
-            Some(p) => p.to_owned(),
-            None => {
-                eprintln!("titleId is not string");
-                return None;
-            }
+        // missing from offline chains
+        let title_id = match value.get("titleId") {
+            Some(p) => match p.as_str() {
+                Some(p) => p.to_owned(),
+                None => {
+                    eprintln!("titleId is not string");
+                    return None;
+                }
+            },
+            None => "".to_owned(),
         };
         Some(Self {
             xuid,
@@ -0,0 +1,82 @@
+use std::io::Result;
+
+use crate::{
+    encoding::{Decode, Encode, VarI32},
+    reader::{self, Reader},
+    writer::Writer,
+};
+
+/// A position or direction in the world, three f32 LE.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Encode for Vec3 {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        self.x.encode(cursor)?;
+        self.y.encode(cursor)?;
+        self.z.encode(cursor)
+    }
+}
+
+impl Decode for Vec3 {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Self {
+            x: f32::decode(cursor)?,
+            y: f32::decode(cursor)?,
+            z: f32::decode(cursor)?,
+        })
+    }
+}
+
+/// A block coordinate, three zigzag varint32.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Encode for BlockPos {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        VarI32(self.x).encode(cursor)?;
+        VarI32(self.y).encode(cursor)?;
+        VarI32(self.z).encode(cursor)
+    }
+}
+
+impl Decode for BlockPos {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Self {
+            x: VarI32::decode(cursor)?.0,
+            y: VarI32::decode(cursor)?.0,
+            z: VarI32::decode(cursor)?.0,
+        })
+    }
+}
+
+/// A chunk column coordinate, two zigzag varint32.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl Encode for ChunkPos {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        VarI32(self.x).encode(cursor)?;
+        VarI32(self.z).encode(cursor)
+    }
+}
+
+impl Decode for ChunkPos {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        Ok(Self {
+            x: VarI32::decode(cursor)?.0,
+            z: VarI32::decode(cursor)?.0,
+        })
+    }
+}
@@ -0,0 +1,70 @@
+use std::{fmt, io::Result};
+
+use crate::{
+    encoding::{Decode, Encode},
+    reader::{self, DecodeError, Reader},
+    writer::Writer,
+};
+
+/// A UUID as sent in game packets: two u64 LE, most significant half first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Uuid(pub u128);
+
+impl Uuid {
+    /// Parses the hyphenated form, e.g. from a pack manifest.
+    pub fn parse(s: &str) -> Option<Self> {
+        let groups: Vec<&str> = s.split('-').collect();
+        let lengths = groups.iter().map(|p| p.len()).collect::<Vec<_>>();
+        if lengths != [8, 4, 4, 4, 12] || !s.chars().all(|c| c == '-' || c.is_ascii_hexdigit()) {
+            return None;
+        }
+        u128::from_str_radix(&groups.concat(), 16).ok().map(Self)
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+}
+
+impl Encode for Uuid {
+    fn encode(&self, cursor: &mut Writer) -> Result<()> {
+        ((self.0 >> 64) as u64).encode(cursor)?;
+        (self.0 as u64).encode(cursor)
+    }
+}
+
+impl Decode for Uuid {
+    fn decode(cursor: &mut Reader) -> reader::Result<Self> {
+        let high = u64::decode(cursor)? as u128;
+        let low = u64::decode(cursor)? as u128;
+        Ok(Self(high << 64 | low))
+    }
+}
+
+impl std::str::FromStr for Uuid {
+    type Err = DecodeError;
+
+    fn from_str(s: &str) -> reader::Result<Self> {
+        Self::parse(s).ok_or_else(|| DecodeError::InvalidValue(format!("Invalid UUID {}", s)))
+    }
+}
+
+#[test]
+fn uuid() {
+    let s = "5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6d";
+    let uuid = Uuid::parse(s).unwrap();
+    assert_eq!(uuid.to_string(), s);
+    assert_eq!(Uuid::parse("5f0c3a9e6c1b4a8e9d3f2b7e4c1a0f6d"), None);
+    assert_eq!(Uuid::parse("5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6"), None);
+}
@@ -0,0 +1,99 @@
+// Protocol numbers of the client releases bers can talk to.
+pub const V1_18_0: u32 = 475;
+pub const V1_18_10: u32 = 486;
+pub const V1_18_30: u32 = 503;
+pub const V1_19_0: u32 = 527;
+pub const V1_19_10: u32 = 534;
+pub const V1_19_20: u32 = 544;
+pub const V1_19_21: u32 = 545;
+pub const V1_19_30: u32 = 554;
+pub const V1_19_40: u32 = 557;
+pub const V1_19_50: u32 = 560;
+pub const V1_19_60: u32 = 567;
+pub const V1_19_63: u32 = 568;
+pub const V1_19_70: u32 = 575;
+pub const V1_19_80: u32 = 582;
+pub const V1_20_0: u32 = 589;
+pub const V1_20_10: u32 = 594;
+pub const V1_20_30: u32 = 618;
+pub const V1_20_40: u32 = 622;
+pub const V1_20_50: u32 = 630;
+pub const V1_20_60: u32 = 649;
+
+/// Supported protocols with their game versions, oldest first.
+pub const SUPPORTED: &[(u32, &str)] = &[
+    (V1_18_0, "1.18.0"),
+    (V1_18_10, "1.18.10"),
+    (V1_18_30, "1.18.30"),
+    (V1_19_0, "1.19.0"),
+    (V1_19_10, "1.19.10"),
+    (V1_19_20, "1.19.20"),
+    (V1_19_21, "1.19.21"),
+    (V1_19_30, "1.19.30"),
+    (V1_19_40, "1.19.40"),
+    (V1_19_50, "1.19.50"),
+    (V1_19_60, "1.19.60"),
+    (V1_19_63, "1.19.63"),
+    (V1_19_70, "1.19.70"),
+    (V1_19_80, "1.19.80"),
+    (V1_20_0, "1.20.0"),
+    (V1_20_10, "1.20.10"),
+    (V1_20_30, "1.20.30"),
+    (V1_20_40, "1.20.40"),
+    (V1_20_50, "1.20.50"),
+    (V1_20_60, "1.20.60"),
+];
+
+pub const OLDEST: u32 = V1_18_0;
+pub const LATEST: u32 = V1_20_60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionMismatch {
+    OutdatedClient,
+    OutdatedServer,
+}
+
+pub fn is_supported(protocol: u32) -> bool {
+    SUPPORTED.iter().any(|(p, _)| *p == protocol)
+}
+
+pub fn game_version(protocol: u32) -> Option<&'static str> {
+    SUPPORTED
+        .iter()
+        .find(|(p, _)| *p == protocol)
+        .map(|(_, v)| *v)
+}
+
+/// Unsupported protocols older than the latest one are told to update the client,
+/// newer ones are told the server is outdated.
+pub fn check(protocol: u32) -> Result<(), VersionMismatch> {
+    if is_supported(protocol) {
+        Ok(())
+    } else if protocol < LATEST {
+        Err(VersionMismatch::OutdatedClient)
+    } else {
+        Err(VersionMismatch::OutdatedServer)
+    }
+}
+
+/// The supported protocol whose packet layout is nearest to `protocol`. Used to encode
+/// the rejection sent to a client whose version is not supported.
+pub fn closest(protocol: u32) -> u32 {
+    SUPPORTED
+        .iter()
+        .rev()
+        .map(|(p, _)| *p)
+        .find(|p| *p <= protocol)
+        .unwrap_or(OLDEST)
+}
+
+#[test]
+fn version() {
+    assert_eq!(check(V1_19_30), Ok(()));
+    assert_eq!(check(471), Err(VersionMismatch::OutdatedClient));
+    assert_eq!(check(600), Err(VersionMismatch::OutdatedClient));
+    assert_eq!(check(LATEST + 1), Err(VersionMismatch::OutdatedServer));
+    assert_eq!(closest(600), V1_20_10);
+    assert_eq!(closest(100), OLDEST);
+    assert_eq!(game_version(V1_20_40), Some("1.20.40"));
+}
@@ -0,0 +1,372 @@
+use std::{collections::HashMap, net::IpAddr, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+// IP entries are only pruned once there are at least this many
+const PRUNE_AT: usize = 4096;
+// hard cap on IP entries, hit when too many IPs were seen within a login window
+const MAX_IPS: usize = 4 * PRUNE_AT;
+
+/// What happens to a connection going over a rate limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Ignore the batch that went over.
+    Drop,
+    /// Hold the batch back until the limits allow it. Batches more than a full window over are
+    /// dropped.
+    Throttle,
+    Kick,
+    /// Kick, and refuse connections from the same IP for this long.
+    Ban(Duration),
+}
+
+/// A limit for each session and one shared by every session from an IP. 0 turns either off.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rate {
+    pub per_session: u32,
+    pub per_ip: u32,
+}
+
+/// Token bucket limits on what clients send. Bursts of up to a full window are allowed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimits {
+    /// `LoginPacket` and `SubClientLogin` per minute, each costs a key exchange or chain check.
+    pub logins_per_minute: Rate,
+    /// Game packets per second, every packet in a batch counts.
+    pub packets_per_second: Rate,
+    /// Batch bytes per second, as received. Checked before a batch is decrypted or decompressed,
+    /// the other limits once its packets are known.
+    pub bytes_per_second: Rate,
+    pub action: Action,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            logins_per_minute: Rate {
+                per_session: 5,
+                per_ip: 30,
+            },
+            packets_per_second: Rate {
+                per_session: 1000,
+                per_ip: 4000,
+            },
+            bytes_per_second: Rate {
+                per_session: 4 * 1024 * 1024,
+                per_ip: 16 * 1024 * 1024,
+            },
+            action: Action::Kick,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Logins,
+    Packets,
+    Bytes,
+}
+
+const KINDS: [Kind; 3] = [Kind::Logins, Kind::Packets, Kind::Bytes];
+
+impl Kind {
+    fn window(self) -> Duration {
+        match self {
+            Kind::Logins => Duration::from_secs(60),
+            Kind::Packets | Kind::Bytes => Duration::from_secs(1),
+        }
+    }
+
+    fn rate(self, limits: &RateLimits) -> Rate {
+        match self {
+            Kind::Logins => limits.logins_per_minute,
+            Kind::Packets => limits.packets_per_second,
+            Kind::Bytes => limits.bytes_per_second,
+        }
+    }
+}
+
+/// Holds up to `rate` tokens and refills them over `window`.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u32, window: Duration, now: Instant) -> Self {
+        Self {
+            capacity: rate as f64,
+            per_second: rate as f64 / window.as_secs_f64(),
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    /// How long until `amount` tokens are there, zero if they are now.
+    pub fn wait(&mut self, amount: u64, now: Instant) -> Duration {
+        if now > self.last {
+            let refill = (now - self.last).as_secs_f64() * self.per_second;
+            self.tokens = (self.tokens + refill).min(self.capacity);
+            self.last = now;
+        }
+        let missing = amount as f64 - self.tokens;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.per_second)
+        }
+    }
+
+    /// Takes `amount` tokens, going below zero if there aren't enough.
+    pub fn take(&mut self, amount: u64) {
+        self.tokens -= amount as f64;
+    }
+}
+
+/// One bucket per `Kind`, `None` where the limit is off.
+pub(crate) struct Buckets([Option<TokenBucket>; 3]);
+
+impl Buckets {
+    fn new(limits: &RateLimits, per_ip: bool, now: Instant) -> Self {
+        Self(KINDS.map(|kind| {
+            let rate = kind.rate(limits);
+            let rate = if per_ip {
+                rate.per_ip
+            } else {
+                rate.per_session
+            };
+            (rate > 0).then(|| TokenBucket::new(rate, kind.window(), now))
+        }))
+    }
+
+    // the longest wait and the kind causing it
+    fn wait(&mut self, amounts: [u64; 3], now: Instant) -> (Duration, Kind) {
+        let mut longest = (Duration::ZERO, Kind::Logins);
+        for (i, bucket) in self.0.iter_mut().enumerate() {
+            if let Some(bucket) = bucket {
+                let wait = bucket.wait(amounts[i], now);
+                if wait > longest.0 {
+                    longest = (wait, KINDS[i]);
+                }
+            }
+        }
+        longest
+    }
+
+    fn take(&mut self, amounts: [u64; 3]) {
+        for (bucket, amount) in self.0.iter_mut().zip(amounts) {
+            if let Some(bucket) = bucket {
+                bucket.take(amount);
+            }
+        }
+    }
+}
+
+struct IpState {
+    buckets: Buckets,
+    last_seen: Instant,
+    banned_until: Option<Instant>,
+}
+
+struct Ips {
+    states: HashMap<IpAddr, IpState>,
+    /// Size the entries are pruned at next. Grows with the busy ones, so they aren't scanned
+    /// again for every new IP.
+    prune_at: usize,
+}
+
+/// Rate limits shared by every connection, holding the per-IP buckets and bans.
+pub(crate) struct Limiter {
+    pub limits: RateLimits,
+    ips: Mutex<Ips>,
+}
+
+impl Limiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            ips: Mutex::new(Ips {
+                states: HashMap::new(),
+                prune_at: PRUNE_AT,
+            }),
+        }
+    }
+
+    fn new_ip(&self, now: Instant) -> IpState {
+        IpState {
+            buckets: Buckets::new(&self.limits, true, now),
+            last_seen: now,
+            banned_until: None,
+        }
+    }
+
+    // the state of `ip`, making room first if it's new
+    fn ip_state<'a>(&self, ips: &'a mut Ips, ip: IpAddr, now: Instant) -> &'a mut IpState {
+        let states = &mut ips.states;
+        if !states.contains_key(&ip) && states.len() >= ips.prune_at {
+            // untouched for a full login window, their buckets are full again
+            states.retain(|_, p| {
+                p.banned_until.is_some_and(|t| t > now)
+                    || now.saturating_duration_since(p.last_seen) < Kind::Logins.window()
+            });
+            if states.len() >= MAX_IPS {
+                // a flood from many IPs, forget the quarter idle the longest, bans included
+                let mut oldest: Vec<(Instant, IpAddr)> =
+                    states.iter().map(|(ip, p)| (p.last_seen, *ip)).collect();
+                oldest.select_nth_unstable(MAX_IPS / 4);
+                for (_, ip) in &oldest[..MAX_IPS / 4] {
+                    states.remove(ip);
+                }
+            }
+            ips.prune_at = (states.len() * 2).clamp(PRUNE_AT, MAX_IPS);
+        }
+        states.entry(ip).or_insert_with(|| self.new_ip(now))
+    }
+
+    /// Buckets for a new session.
+    pub fn session(&self) -> Buckets {
+        Buckets::new(&self.limits, false, Instant::now())
+    }
+
+    /// Takes logins, packets and bytes from the session's and the IP's buckets. Returns how
+    /// long the caller has to hold the batch back, only ever non-zero with `Action::Throttle`,
+    /// or the limit that was broken. Nothing is taken when a limit is broken.
+    pub fn take(
+        &self,
+        session: &mut Buckets,
+        ip: IpAddr,
+        amounts: [u64; 3],
+        now: Instant,
+    ) -> Result<Duration, Kind> {
+        let mut ips = self.ips.lock().unwrap();
+        let state = self.ip_state(&mut ips, ip, now);
+        state.last_seen = now;
+
+        let (session_wait, session_kind) = session.wait(amounts, now);
+        let (ip_wait, ip_kind) = state.buckets.wait(amounts, now);
+        let (wait, kind) = if session_wait >= ip_wait {
+            (session_wait, session_kind)
+        } else {
+            (ip_wait, ip_kind)
+        };
+        if wait.is_zero() || (self.limits.action == Action::Throttle && wait <= kind.window()) {
+            session.take(amounts);
+            state.buckets.take(amounts);
+            Ok(wait)
+        } else {
+            Err(kind)
+        }
+    }
+
+    pub fn ban(&self, ip: IpAddr, duration: Duration) {
+        let now = Instant::now();
+        let mut ips = self.ips.lock().unwrap();
+        let state = self.ip_state(&mut ips, ip, now);
+        state.banned_until = Some(now + duration);
+    }
+
+    pub fn banned(&self, ip: IpAddr) -> bool {
+        let ips = self.ips.lock().unwrap();
+        ips.states
+            .get(&ip)
+            .and_then(|p| p.banned_until)
+            .is_some_and(|t| t > Instant::now())
+    }
+}
+
+#[test]
+fn rate_limit() {
+    let now = Instant::now();
+    let mut bucket = TokenBucket::new(10, Duration::from_secs(1), now);
+    assert_eq!(bucket.wait(10, now), Duration::ZERO);
+    bucket.take(10);
+    assert_eq!(bucket.wait(1, now), Duration::from_millis(100));
+    assert_eq!(
+        bucket.wait(1, now + Duration::from_millis(100)),
+        Duration::ZERO
+    );
+    // never refills past its capacity
+    assert!(bucket.wait(11, now + Duration::from_secs(10)) > Duration::ZERO);
+
+    let limits = RateLimits {
+        packets_per_second: Rate {
+            per_session: 4,
+            per_ip: 6,
+        },
+        ..RateLimits::default()
+    };
+    let limiter = Limiter::new(limits);
+    let ip = IpAddr::from([127, 0, 0, 1]);
+    let mut first = limiter.session();
+    let mut second = limiter.session();
+    assert_eq!(
+        limiter.take(&mut first, ip, [0, 4, 0], now),
+        Ok(Duration::ZERO)
+    );
+    assert_eq!(
+        limiter.take(&mut first, ip, [0, 1, 0], now),
+        Err(Kind::Packets)
+    );
+    assert_eq!(
+        limiter.take(&mut second, ip, [0, 2, 0], now),
+        Ok(Duration::ZERO)
+    );
+    // the session still has room, the IP doesn't
+    assert_eq!(
+        limiter.take(&mut second, ip, [0, 1, 0], now),
+        Err(Kind::Packets)
+    );
+    let other = IpAddr::from([127, 0, 0, 2]);
+    assert_eq!(
+        limiter.take(&mut second, other, [0, 1, 0], now),
+        Ok(Duration::ZERO)
+    );
+
+    let limiter = Limiter::new(RateLimits {
+        action: Action::Throttle,
+        ..limits
+    });
+    let mut session = limiter.session();
+    assert_eq!(
+        limiter.take(&mut session, ip, [0, 6, 0], now),
+        Ok(Duration::from_millis(500))
+    );
+    assert_eq!(
+        limiter.take(&mut session, ip, [0, 6, 0], now),
+        Err(Kind::Packets)
+    );
+
+    assert!(!limiter.banned(ip));
+    limiter.ban(ip, Duration::from_secs(60));
+    assert!(limiter.banned(ip));
+    assert!(!limiter.banned(other));
+}
+
+#[test]
+fn ip_cap() {
+    let now = Instant::now();
+    let limiter = Limiter::new(RateLimits::default());
+    let ip = |i: usize| IpAddr::from((i as u32).to_be_bytes());
+    // all within a login window, nothing can be pruned
+    for i in 0..MAX_IPS {
+        let at = now + Duration::from_micros(i as u64);
+        assert!(limiter
+            .take(&mut limiter.session(), ip(i), [0, 1, 0], at)
+            .is_ok());
+    }
+    assert_eq!(limiter.ips.lock().unwrap().states.len(), MAX_IPS);
+
+    let at = now + Duration::from_secs(1);
+    assert!(limiter
+        .take(&mut limiter.session(), ip(MAX_IPS), [0, 1, 0], at)
+        .is_ok());
+    let ips = &limiter.ips.lock().unwrap().states;
+    assert_eq!(ips.len(), MAX_IPS - MAX_IPS / 4 + 1);
+    assert!(!ips.contains_key(&ip(0)));
+    assert!(!ips.contains_key(&ip(MAX_IPS / 4 - 1)));
+    assert!(ips.contains_key(&ip(MAX_IPS / 4)));
+    assert!(ips.contains_key(&ip(MAX_IPS)));
+}
@@ -1,109 +1,292 @@
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
-use minecraft_varint::VarIntRead;
-use std::{
-    io::{Cursor, Error, Read, Result},
-    str,
-};
+use std::{fmt, str};
 
 pub enum Endian {
     Big,
     Little,
 }
 
+/// Longest string or list a `Reader` accepts by default. Logins with large skins are the
+/// biggest legitimate payloads and stay well under it.
+pub const MAX_LENGTH: usize = 4 * 1024 * 1024;
+
+/// Why bytes from the network could not be read.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The input ended before the value did.
+    Truncated {
+        needed: usize,
+        remaining: usize,
+    },
+    /// A varint with more bytes than its type can hold.
+    OverlongVarInt,
+    InvalidUtf8(str::Utf8Error),
+    /// A length prefix larger than the reader's limit.
+    LengthOverLimit {
+        length: u64,
+        limit: usize,
+    },
+    /// Bytes left over after a packet was fully read.
+    TrailingBytes(usize),
+    /// A value outside the ones the field can take, e.g. an unknown enum discriminant.
+    InvalidValue(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated { needed, remaining } => {
+                write!(f, "Truncated: needed {} bytes, {} left", needed, remaining)
+            }
+            DecodeError::OverlongVarInt => write!(f, "OverlongVarInt"),
+            DecodeError::InvalidUtf8(e) => write!(f, "InvalidUtf8 {}", e),
+            DecodeError::LengthOverLimit { length, limit } => {
+                write!(f, "LengthOverLimit: {} over {}", length, limit)
+            }
+            DecodeError::TrailingBytes(n) => write!(f, "TrailingBytes: {} left", n),
+            DecodeError::InvalidValue(e) => write!(f, "InvalidValue {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for std::io::Error {
+    fn from(e: DecodeError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, DecodeError>;
+
+/// Bounds-checked reads over a byte slice. Every read either returns a value or a
+/// `DecodeError`; no input can make it panic.
 #[derive(Clone)]
 pub struct Reader<'a> {
     pub buff: &'a [u8],
-    cursor: Cursor<&'a [u8]>,
+    pos: usize,
+    /// Longest string or list accepted.
+    pub max_length: usize,
 }
 
 impl<'a> Reader<'a> {
     pub fn new(buf: &'a [u8]) -> Self {
         Self {
             buff: buf,
-            cursor: Cursor::new(buf),
+            pos: 0,
+            max_length: MAX_LENGTH,
         }
     }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let remaining = self.remaining();
+        if n > remaining {
+            return Err(DecodeError::Truncated {
+                needed: n,
+                remaining,
+            });
+        }
+        let bytes = &self.buff[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
     pub fn read(&mut self, buf: &mut [u8]) -> Result<()> {
-        self.cursor.read_exact(buf)?;
+        buf.copy_from_slice(self.take(buf.len())?);
         Ok(())
     }
+
+    /// Borrows the next `n` bytes.
+    pub fn read_bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        self.take(n)
+    }
+
     pub fn read_u8(&mut self) -> Result<u8> {
-        self.cursor.read_u8()
+        Ok(self.take(1)?[0])
     }
 
     pub fn read_u16(&mut self, n: Endian) -> Result<u16> {
-        match n {
-            Endian::Big => self.cursor.read_u16::<BigEndian>(),
-            Endian::Little => self.cursor.read_u16::<LittleEndian>(),
-        }
+        let bytes = self.take_array()?;
+        Ok(match n {
+            Endian::Big => u16::from_be_bytes(bytes),
+            Endian::Little => u16::from_le_bytes(bytes),
+        })
     }
 
     pub fn read_u32(&mut self, n: Endian) -> Result<u32> {
-        match n {
-            Endian::Big => self.cursor.read_u32::<BigEndian>(),
-            Endian::Little => self.cursor.read_u32::<LittleEndian>(),
-        }
+        let bytes = self.take_array()?;
+        Ok(match n {
+            Endian::Big => u32::from_be_bytes(bytes),
+            Endian::Little => u32::from_le_bytes(bytes),
+        })
     }
 
     pub fn read_i32(&mut self, n: Endian) -> Result<i32> {
-        match n {
-            Endian::Big => self.cursor.read_i32::<BigEndian>(),
-            Endian::Little => self.cursor.read_i32::<LittleEndian>(),
-        }
+        Ok(self.read_u32(n)? as i32)
+    }
+
+    pub fn read_f32(&mut self, n: Endian) -> Result<f32> {
+        Ok(f32::from_bits(self.read_u32(n)?))
+    }
+
+    pub fn read_f64(&mut self, n: Endian) -> Result<f64> {
+        Ok(f64::from_bits(self.read_u64(n)?))
     }
 
     pub fn read_u64(&mut self, n: Endian) -> Result<u64> {
-        match n {
-            Endian::Big => self.cursor.read_u64::<BigEndian>(),
-            Endian::Little => self.cursor.read_u64::<LittleEndian>(),
-        }
+        let bytes = self.take_array()?;
+        Ok(match n {
+            Endian::Big => u64::from_be_bytes(bytes),
+            Endian::Little => u64::from_le_bytes(bytes),
+        })
     }
     pub fn read_i64(&mut self, n: Endian) -> Result<i64> {
-        match n {
-            Endian::Big => self.cursor.read_i64::<BigEndian>(),
-            Endian::Little => self.cursor.read_i64::<LittleEndian>(),
-        }
+        Ok(self.read_u64(n)? as i64)
     }
 
     pub fn read_u24(&mut self, n: Endian) -> Result<u32> {
-        match n {
-            Endian::Big => self.cursor.read_u24::<BigEndian>(),
-            Endian::Little => self.cursor.read_u24::<LittleEndian>(),
+        let [a, b, c] = self.take_array()?;
+        Ok(match n {
+            Endian::Big => u32::from_be_bytes([0, a, b, c]),
+            Endian::Little => u32::from_le_bytes([a, b, c, 0]),
+        })
+    }
+
+    // 7 bits per byte, low groups first, at most `bits` bits in total
+    fn read_varint(&mut self, bits: u32) -> Result<u64> {
+        let mut v = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = self.read_u8()?;
+            let group = (b & 0x7f) as u64;
+            if shift + 7 > bits && group >> (bits - shift) != 0 {
+                return Err(DecodeError::OverlongVarInt);
+            }
+            v |= group << shift;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+            shift += 7;
+            if shift >= bits {
+                return Err(DecodeError::OverlongVarInt);
+            }
         }
     }
 
     pub fn read_vari32(&mut self) -> Result<i32> {
-        self.cursor.read_var_i32()
+        let v = self.read_varu32()?;
+        Ok(((v >> 1) as i32) ^ -((v & 1) as i32))
     }
     pub fn read_varu32(&mut self) -> Result<u32> {
-        self.cursor.read_var_u32()
+        Ok(self.read_varint(32)? as u32)
     }
     pub fn read_vari64(&mut self) -> Result<i64> {
-        self.cursor.read_var_i64()
+        let v = self.read_varu64()?;
+        Ok(((v >> 1) as i64) ^ -((v & 1) as i64))
     }
     pub fn read_varu64(&mut self) -> Result<u64> {
-        self.cursor.read_var_u64()
+        self.read_varint(64)
     }
 
-    pub fn read_string(&mut self) -> Result<String> {
-        let size = self.cursor.read_u32::<LittleEndian>()?;
-        let str_buf = &self.buff[self.pos() as usize..(self.pos() + size as u64) as usize];
-        self.next(size.into());
-        match str::from_utf8(str_buf) {
+    /// Checks a string or list length from the wire against `max_length`.
+    pub fn check_length(&self, length: u64) -> Result<usize> {
+        if length > self.max_length as u64 {
+            return Err(DecodeError::LengthOverLimit {
+                length,
+                limit: self.max_length,
+            });
+        }
+        Ok(length as usize)
+    }
+
+    fn read_utf8(&mut self, length: u64) -> Result<String> {
+        let length = self.check_length(length)?;
+        match str::from_utf8(self.take(length)?) {
             Ok(p) => Ok(p.to_owned()),
-            Err(e) => Err(Error::new(std::io::ErrorKind::Other, e.to_string())),
+            Err(e) => Err(DecodeError::InvalidUtf8(e)),
         }
     }
 
-    pub fn next(&mut self, n: u64) {
-        self.cursor.set_position(self.cursor.position() + n);
+    /// String with a varuint32 length, the usual one in game packets.
+    pub fn read_string(&mut self) -> Result<String> {
+        let size = self.read_varu32()?;
+        self.read_utf8(size.into())
+    }
+
+    /// String with a u32 LE length, as found in the login request.
+    pub fn read_le_string(&mut self) -> Result<String> {
+        let size = self.read_u32(Endian::Little)?;
+        self.read_utf8(size.into())
     }
 
     pub fn pos(&self) -> u64 {
-        self.cursor.position()
+        self.pos as u64
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buff.len() - self.pos
     }
 
-    pub fn get_cursor(&mut self) -> &mut Cursor<&'a [u8]> {
-        &mut self.cursor
+    /// Errors if anything is left unread.
+    pub fn finish(&self) -> Result<()> {
+        match self.remaining() {
+            0 => Ok(()),
+            n => Err(DecodeError::TrailingBytes(n)),
+        }
     }
 }
+
+#[test]
+fn reader() {
+    let mut reader = Reader::new(&[0xff, 0xff, 0xff, 0xff, 0x0f]);
+    assert_eq!(reader.read_varu32().unwrap(), u32::MAX);
+    reader.finish().unwrap();
+
+    let mut reader = Reader::new(&[0xff, 0xff, 0xff, 0xff, 0x1f]);
+    assert!(matches!(
+        reader.read_varu32(),
+        Err(DecodeError::OverlongVarInt)
+    ));
+    let mut reader = Reader::new(&[0x80, 0x80, 0x80, 0x80, 0x80, 0x00]);
+    assert!(matches!(
+        reader.read_varu32(),
+        Err(DecodeError::OverlongVarInt)
+    ));
+
+    let mut reader = Reader::new(&[0x03]);
+    assert_eq!(reader.read_vari32().unwrap(), -2);
+
+    // length past the end of the input
+    let mut reader = Reader::new(&[0x10, 0, 0, 0, b'a']);
+    assert!(matches!(
+        reader.read_le_string(),
+        Err(DecodeError::Truncated { .. })
+    ));
+    let mut reader = Reader::new(&[0xff, 0xff, 0xff, 0x7f, b'a']);
+    assert!(matches!(
+        reader.read_string(),
+        Err(DecodeError::LengthOverLimit { .. })
+    ));
+
+    let mut reader = Reader::new(&[0x02, 0xc3, 0x28]);
+    assert!(matches!(
+        reader.read_string(),
+        Err(DecodeError::InvalidUtf8(_))
+    ));
+
+    let mut reader = Reader::new(&[0x01, 0x02]);
+    reader.read_u8().unwrap();
+    assert!(matches!(
+        reader.finish(),
+        Err(DecodeError::TrailingBytes(1))
+    ));
+    assert!(matches!(
+        reader.read_u32(Endian::Little),
+        Err(DecodeError::Truncated { .. })
+    ));
+}
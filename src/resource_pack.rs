@@ -0,0 +1,565 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{BufReader, Cursor, Error, ErrorKind, Read, Result, Seek, Write},
+    path::Path,
+};
+
+use aes::Aes256;
+use cfb8::cipher::{AsyncStreamCipher, KeyIvInit};
+use rand::{distributions::Alphanumeric, Rng};
+use ring::digest::{digest, Context, SHA256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::protocol::{
+    packets::{
+        resource_pack_data_info::PackType,
+        resource_pack_stack::StackPack,
+        resource_packs_info::{PackInfo, PackUrl},
+    },
+    types::uuid::Uuid,
+};
+
+/// Size of the chunks packs are sent in.
+pub const CHUNK_SIZE: u32 = 1024 * 1024;
+
+/// Length of content keys and of the per-file keys in `contents.json`.
+pub const KEY_SIZE: usize = 32;
+
+const CONTENTS_MAGIC: u32 = 0x9bcf_b9fc;
+// the encrypted file list starts after this header
+const CONTENTS_HEADER_SIZE: usize = 0x100;
+
+#[derive(Deserialize)]
+struct Manifest {
+    header: ManifestHeader,
+    #[serde(default)]
+    modules: Vec<ManifestModule>,
+}
+
+#[derive(Deserialize)]
+struct ManifestHeader {
+    uuid: String,
+    version: Value,
+}
+
+#[derive(Deserialize)]
+struct ManifestModule {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+// decrypted `contents.json`, files without a key are stored plain
+#[derive(Deserialize, Serialize)]
+struct Contents {
+    content: Vec<ContentEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct ContentEntry {
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+type Archive<R> = zip::ZipArchive<R>;
+
+fn open<R: Read + Seek>(reader: R) -> Result<Archive<R>> {
+    zip::ZipArchive::new(reader).map_err(|e| invalid(format!("invalid pack archive {}", e)))
+}
+
+// folder holding manifest.json, `""` or `name/`
+fn pack_root<R: Read + Seek>(archive: &Archive<R>) -> Result<String> {
+    let name = archive
+        .file_names()
+        .filter(|p| *p == "manifest.json" || p.ends_with("/manifest.json"))
+        .min_by_key(|p| p.matches('/').count());
+    match name {
+        Some(p) if p.matches('/').count() <= 1 => {
+            Ok(p[..p.len() - "manifest.json".len()].to_owned())
+        }
+        _ => Err(invalid("pack has no manifest.json".to_owned())),
+    }
+}
+
+fn read_file<R: Read + Seek>(archive: &mut Archive<R>, name: &str) -> Result<Vec<u8>> {
+    let mut file = archive
+        .by_name(name)
+        .map_err(|e| invalid(format!("invalid pack archive {}", e)))?;
+    let mut data = vec![];
+    file.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn check_key(key: &str) -> Result<()> {
+    if key.len() != KEY_SIZE || !key.is_ascii() {
+        return Err(invalid(format!(
+            "content keys are {} ASCII characters",
+            KEY_SIZE
+        )));
+    }
+    Ok(())
+}
+
+// AES-256-CFB8 keyed with the key's bytes, the IV is its first 16 bytes
+fn encrypt_data(key: &str, data: &mut [u8]) {
+    let key = key.as_bytes();
+    cfb8::Encryptor::<Aes256>::new(key.into(), key[..16].into()).encrypt(data);
+}
+
+fn decrypt_data(key: &str, data: &mut [u8]) {
+    let key = key.as_bytes();
+    cfb8::Decryptor::<Aes256>::new(key.into(), key[..16].into()).decrypt(data);
+}
+
+// content ID from the header and the decrypted file list
+fn read_contents(mut data: Vec<u8>, key: &str) -> Result<(String, Contents)> {
+    if data.len() < CONTENTS_HEADER_SIZE || data[4..8] != CONTENTS_MAGIC.to_le_bytes() {
+        return Err(invalid("contents.json is not encrypted".to_owned()));
+    }
+    let id_len = data[16] as usize;
+    let content_id =
+        String::from_utf8_lossy(&data[17..(17 + id_len).min(CONTENTS_HEADER_SIZE)]).into_owned();
+    let list = &mut data[CONTENTS_HEADER_SIZE..];
+    decrypt_data(key, list);
+    let contents = serde_json::from_slice(list)
+        .map_err(|_| invalid("wrong content key for contents.json".to_owned()))?;
+    Ok((content_id, contents))
+}
+
+fn write_contents(content_id: &str, contents: &Contents, key: &str) -> Result<Vec<u8>> {
+    let mut list = serde_json::to_vec(contents)?;
+    encrypt_data(key, &mut list);
+    let mut data = Vec::with_capacity(CONTENTS_HEADER_SIZE + list.len());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&CONTENTS_MAGIC.to_le_bytes());
+    data.extend_from_slice(&[0; 8]);
+    data.push(content_id.len() as u8);
+    data.extend_from_slice(content_id.as_bytes());
+    data.resize(CONTENTS_HEADER_SIZE, 0);
+    data.extend_from_slice(&list);
+    Ok(data)
+}
+
+/// A random key for `encrypt`.
+pub fn generate_key() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(KEY_SIZE)
+        .map(char::from)
+        .collect()
+}
+
+/// Encrypts a plain pack archive the way marketplace packs are. Every file but the manifest
+/// and icon is encrypted with its own random key, and the keys go in a `contents.json`
+/// encrypted with `content_key`. Load the result with `ResourcePack::from_encrypted`.
+pub fn encrypt(archive: &[u8], content_key: &str) -> Result<Vec<u8>> {
+    check_key(content_key)?;
+    let uuid = ResourcePack::from_bytes(archive.to_vec())?.uuid;
+    let mut archive = open(Cursor::new(archive))?;
+    let root = pack_root(&archive)?;
+
+    let mut encrypted = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options = zip::write::FileOptions::default();
+    let mut contents = Contents { content: vec![] };
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| invalid(format!("invalid pack archive {}", e)))?;
+        if file.is_dir() {
+            continue;
+        }
+        let name = file.name().to_owned();
+        let mut data = vec![];
+        file.read_to_end(&mut data)?;
+        let path = match name.strip_prefix(root.as_str()) {
+            Some("contents.json") => continue,
+            Some(p) => p.to_owned(),
+            // outside the pack folder, not part of the pack
+            None => {
+                encrypted.start_file(name, options)?;
+                encrypted.write_all(&data)?;
+                continue;
+            }
+        };
+        let key = match path.as_str() {
+            "manifest.json" | "pack_icon.png" => None,
+            _ => {
+                let key = generate_key();
+                encrypt_data(&key, &mut data);
+                Some(key)
+            }
+        };
+        contents.content.push(ContentEntry { path, key });
+        encrypted.start_file(name, options)?;
+        encrypted.write_all(&data)?;
+    }
+    let data = write_contents(&uuid.to_string(), &contents, content_key)?;
+    encrypted.start_file(format!("{}contents.json", root), options)?;
+    encrypted.write_all(&data)?;
+    Ok(encrypted.finish()?.into_inner())
+}
+
+/// A behavior or resource pack archive as sent to clients.
+#[derive(Clone, Debug)]
+pub struct ResourcePack {
+    pub uuid: Uuid,
+    /// `major.minor.patch` from the manifest.
+    pub version: String,
+    /// `Behavior` for packs with data or script modules, `Resources` otherwise.
+    pub pack_type: PackType,
+    pub has_scripts: bool,
+    url: Option<String>,
+    // `None` for packs delivered by URL
+    content: Option<Vec<u8>>,
+    size: u64,
+    hash: [u8; 32],
+    content_key: String,
+    content_id: String,
+}
+
+impl ResourcePack {
+    /// Loads a `.mcpack` or `.zip` file. If there is a key file next to it, named like the pack
+    /// plus `.key`, the pack is loaded as encrypted.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let key_path = key_path(path);
+        if Path::new(&key_path).is_file() {
+            Self::load_encrypted(path, key_path)
+        } else {
+            Self::from_bytes(fs::read(path)?)
+        }
+    }
+
+    /// Loads a pack clients download from `url` over HTTP instead of asking for chunks, which
+    /// clients only do from 1.20.30 on. The URL must serve the same bytes, see
+    /// `pack_server::PackServer` for local testing. The file is only streamed to hash it, the
+    /// archive isn't kept in memory. A key file next to it is picked up as in `load`.
+    pub fn load_url<P: AsRef<Path>>(path: P, url: String) -> Result<Self> {
+        let path = path.as_ref();
+        let key_path = key_path(path);
+        let content_key = if Path::new(&key_path).is_file() {
+            Some(fs::read_to_string(key_path)?.trim().to_owned())
+        } else {
+            None
+        };
+        let mut file = File::open(path)?;
+        let mut pack = Self::from_archive(BufReader::new(&file), content_key)?;
+        file.rewind()?;
+        let mut context = Context::new(&SHA256);
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            context.update(&buf[..n]);
+            pack.size += n as u64;
+        }
+        pack.hash.copy_from_slice(context.finish().as_ref());
+        pack.url = Some(url);
+        Ok(pack)
+    }
+
+    /// Loads an encrypted pack and the file holding its content key.
+    pub fn load_encrypted<P: AsRef<Path>, K: AsRef<Path>>(path: P, key_path: K) -> Result<Self> {
+        let key = fs::read_to_string(key_path)?;
+        Self::from_encrypted(fs::read(path)?, key.trim().to_owned())
+    }
+
+    /// Loads every `.mcpack` and `.zip` file in a directory, in file name order, along with
+    /// their key files.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>> {
+        let mut paths = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|p| p.to_str());
+            if matches!(extension, Some("mcpack" | "zip")) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths.into_iter().map(Self::load).collect()
+    }
+
+    /// Reads the manifest of an archive. It may sit at the root or in a single top folder.
+    pub fn from_bytes(content: Vec<u8>) -> Result<Self> {
+        Self::with_content(content, None)
+    }
+
+    /// Reads an encrypted archive, checking `content_key` against its `contents.json`. The
+    /// archive is sent as is, clients decrypt it with the key from `ResourcePacksInfo`.
+    pub fn from_encrypted(content: Vec<u8>, content_key: String) -> Result<Self> {
+        Self::with_content(content, Some(content_key))
+    }
+
+    fn with_content(content: Vec<u8>, content_key: Option<String>) -> Result<Self> {
+        let mut pack = Self::from_archive(Cursor::new(&content[..]), content_key)?;
+        pack.hash
+            .copy_from_slice(digest(&SHA256, &content).as_ref());
+        pack.size = content.len() as u64;
+        pack.content = Some(content);
+        Ok(pack)
+    }
+
+    // everything but the content, size and hash
+    fn from_archive<R: Read + Seek>(reader: R, content_key: Option<String>) -> Result<Self> {
+        if let Some(key) = &content_key {
+            check_key(key)?;
+        }
+        let mut archive = open(reader)?;
+        let root = pack_root(&archive)?;
+        let manifest = read_file(&mut archive, &format!("{}manifest.json", root))?;
+        let manifest: Manifest = serde_json::from_slice(&manifest)
+            .map_err(|e| invalid(format!("invalid manifest.json {}", e)))?;
+
+        let uuid = match Uuid::parse(&manifest.header.uuid) {
+            Some(p) => p,
+            None => {
+                return Err(invalid(format!(
+                    "invalid pack UUID {}",
+                    manifest.header.uuid
+                )))
+            }
+        };
+        let version = match &manifest.header.version {
+            Value::Array(parts) if parts.len() == 3 && parts.iter().all(Value::is_u64) => parts
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join("."),
+            Value::String(p) => p.clone(),
+            _ => return Err(invalid("invalid pack version".to_owned())),
+        };
+        let kinds: Vec<&str> = manifest.modules.iter().map(|p| p.kind.as_str()).collect();
+        let has_scripts = kinds.iter().any(|p| matches!(*p, "script" | "javascript"));
+        let pack_type = if has_scripts || kinds.contains(&"data") {
+            PackType::Behavior
+        } else if kinds.contains(&"resources") {
+            PackType::Resources
+        } else {
+            return Err(invalid(format!("unsupported pack modules {:?}", kinds)));
+        };
+
+        let (content_key, content_id) = match content_key {
+            Some(key) => {
+                let contents = read_file(&mut archive, &format!("{}contents.json", root))?;
+                let content_id = read_contents(contents, &key)?.0;
+                (key, content_id)
+            }
+            None => (String::new(), String::new()),
+        };
+        Ok(Self {
+            uuid,
+            version,
+            pack_type,
+            has_scripts,
+            url: None,
+            content: None,
+            size: 0,
+            hash: [0; 32],
+            content_key,
+            content_id,
+        })
+    }
+
+    /// `uuid_version`, how packets name the pack.
+    pub fn id(&self) -> String {
+        format!("{}_{}", self.uuid, self.version)
+    }
+
+    /// `None` for packs delivered by URL.
+    pub fn content(&self) -> Option<&[u8]> {
+        self.content.as_deref()
+    }
+
+    /// Where clients download the pack, for packs from `load_url`.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Size of the archive in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Empty for packs that aren't encrypted.
+    pub fn content_key(&self) -> &str {
+        &self.content_key
+    }
+
+    /// SHA-256 of the archive.
+    pub fn hash(&self) -> &[u8; 32] {
+        &self.hash
+    }
+
+    pub fn chunk_count(&self) -> u32 {
+        self.size.div_ceil(CHUNK_SIZE as u64) as u32
+    }
+
+    /// Chunk `index`, or `None` past the end and for packs delivered by URL.
+    pub fn chunk(&self, index: u32) -> Option<&[u8]> {
+        let content = self.content.as_ref()?;
+        content.chunks(CHUNK_SIZE as usize).nth(index as usize)
+    }
+
+    pub fn info(&self) -> PackInfo {
+        PackInfo {
+            uuid: self.uuid.to_string(),
+            version: self.version.clone(),
+            size: self.size,
+            content_key: self.content_key.clone(),
+            sub_pack_name: String::new(),
+            content_identity: self.content_id.clone(),
+            has_scripts: self.has_scripts,
+            rtx_enabled: false,
+        }
+    }
+
+    /// `None` for packs without a URL.
+    pub fn pack_url(&self) -> Option<PackUrl> {
+        self.url.as_ref().map(|url| PackUrl {
+            uuid_version: self.id(),
+            url: url.clone(),
+        })
+    }
+
+    pub fn stack(&self) -> StackPack {
+        StackPack {
+            uuid: self.uuid.to_string(),
+            version: self.version.clone(),
+            sub_pack_name: String::new(),
+        }
+    }
+
+    /// Whether `id` from a `ResourcePackClientResponse` names this pack. Clients send
+    /// `uuid_version`, or only the UUID.
+    pub fn matches(&self, id: &str) -> bool {
+        let uuid = id.split('_').next().unwrap_or_default();
+        Uuid::parse(uuid) == Some(self.uuid)
+            && id.get(uuid.len() + 1..).is_none_or(|p| p == self.version)
+    }
+}
+
+fn key_path(path: &Path) -> OsString {
+    let mut key_path = path.as_os_str().to_owned();
+    key_path.push(".key");
+    key_path
+}
+
+#[cfg(test)]
+pub(crate) fn test_pack(folder: &str, modules: &str, size: usize) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut archive = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    archive
+        .start_file(format!("{}manifest.json", folder), options)
+        .unwrap();
+    write!(
+        archive,
+        r#"{{"format_version":2,"header":{{"uuid":"5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6d","version":[1,2,3]}},"modules":[{}]}}"#,
+        modules
+    )
+    .unwrap();
+    archive
+        .start_file(format!("{}blob", folder), options)
+        .unwrap();
+    archive.write_all(&vec![7; size]).unwrap();
+    archive.finish().unwrap().into_inner()
+}
+
+#[test]
+fn resource_pack() {
+    let pack = ResourcePack::from_bytes(test_pack(
+        "pack/",
+        r#"{"type":"resources"}"#,
+        CHUNK_SIZE as usize,
+    ))
+    .unwrap();
+    assert_eq!(pack.id(), "5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6d_1.2.3");
+    assert_eq!(pack.pack_type, PackType::Resources);
+    assert!(!pack.has_scripts);
+    assert_eq!(pack.chunk_count(), 2);
+    let chunks = [pack.chunk(0).unwrap(), pack.chunk(1).unwrap()].concat();
+    assert_eq!(chunks, pack.content().unwrap());
+    assert_eq!(pack.chunk(2), None);
+    assert_eq!(pack.hash(), digest(&SHA256, &chunks).as_ref());
+    assert!(pack.matches("5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6d_1.2.3"));
+    assert!(pack.matches("5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6d"));
+    assert!(!pack.matches("5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6d_1.0.0"));
+
+    let pack =
+        ResourcePack::from_bytes(test_pack("", r#"{"type":"data"},{"type":"script"}"#, 0)).unwrap();
+    assert_eq!(pack.pack_type, PackType::Behavior);
+    assert!(pack.has_scripts);
+
+    assert!(ResourcePack::from_bytes(test_pack("a/b/", r#"{"type":"data"}"#, 0)).is_err());
+    assert!(ResourcePack::from_bytes(vec![1, 2, 3]).is_err());
+}
+
+#[test]
+fn encrypted_pack() {
+    let key = generate_key();
+    let content = encrypt(&test_pack("pack/", r#"{"type":"resources"}"#, 100), &key).unwrap();
+    let pack = ResourcePack::from_encrypted(content.clone(), key.clone()).unwrap();
+    assert_eq!(pack.content().unwrap(), content);
+    let info = pack.info();
+    assert_eq!(info.content_key, key);
+    assert_eq!(
+        info.content_identity,
+        "5f0c3a9e-6c1b-4a8e-9d3f-2b7e4c1a0f6d"
+    );
+
+    let mut archive = open(Cursor::new(&content[..])).unwrap();
+    let contents = read_file(&mut archive, "pack/contents.json").unwrap();
+    let (_, contents) = read_contents(contents, &key).unwrap();
+    assert_eq!(contents.content.len(), 2);
+    for entry in contents.content {
+        let mut data = read_file(&mut archive, &format!("pack/{}", entry.path)).unwrap();
+        match entry.key {
+            Some(key) => {
+                assert_eq!(entry.path, "blob");
+                decrypt_data(&key, &mut data);
+                assert_eq!(data, vec![7; 100]);
+            }
+            None => assert_eq!(entry.path, "manifest.json"),
+        }
+    }
+
+    assert!(ResourcePack::from_encrypted(content.clone(), generate_key()).is_err());
+    assert!(ResourcePack::from_encrypted(content, "short".to_owned()).is_err());
+    let plain = test_pack("", r#"{"type":"resources"}"#, 0);
+    assert!(ResourcePack::from_encrypted(plain, key).is_err());
+}
+
+#[test]
+fn url_pack() {
+    let dir = std::env::temp_dir().join(format!("bers-url-pack-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let key = generate_key();
+    let content = encrypt(&test_pack("pack/", r#"{"type":"resources"}"#, 100), &key).unwrap();
+    let path = dir.join("pack.mcpack");
+    fs::write(&path, &content).unwrap();
+    fs::write(dir.join("pack.mcpack.key"), &key).unwrap();
+
+    let url = "https://cdn.example.com/pack.mcpack".to_owned();
+    let pack = ResourcePack::load_url(&path, url.clone()).unwrap();
+    let in_memory = ResourcePack::load(&path).unwrap();
+    assert_eq!(pack.url(), Some(url.as_str()));
+    assert_eq!(pack.content(), None);
+    assert_eq!(pack.chunk(0), None);
+    assert_eq!(pack.size(), content.len() as u64);
+    assert_eq!(pack.chunk_count(), 1);
+    assert_eq!(pack.hash(), in_memory.hash());
+    assert_eq!(pack.info(), in_memory.info());
+    assert_eq!(pack.pack_url().unwrap().url, url);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
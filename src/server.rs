@@ -1,70 +1,250 @@
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 
 use raknet::{RaknetEvent, Server};
-use tokio::sync::Mutex;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::{
+    config::Config,
+    connection::{Connection, Inbound, Outbound, Slots, INBOUND_QUEUE},
+    motd::Motd,
+    protocol::packets::disconnect::DisconnectReason,
+    rate_limit::{Buckets, Limiter},
+    session::{Session, SessionId},
+};
+
+// how long to wait before asking RakNet again after it had nothing for us
+const IDLE_POLL: Duration = Duration::from_millis(10);
+// how long shutdown waits for players to receive their Disconnect
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) fn raknet_error<E: std::fmt::Display>(e: E) -> std::io::Error {
+    std::io::Error::other(e.to_string())
+}
+
+// a connection task as the socket task sees it
+struct Route {
+    sender: mpsc::Sender<Inbound>,
+    /// Charged with the bytes of each batch before it is forwarded.
+    buckets: Buckets,
+}
+
+pub enum Event {
+    /// A player finished login and the encrypted session is ready. Split-screen players
+    /// joining an existing connection get their own `Connected`.
+    Connected(Session),
+    /// A game packet from an authenticated player. The payload starts with the packet header,
+    /// so it can be passed straight to `protocol::packets::decode`.
+    Packet(SessionId, Vec<u8>),
+    /// An authenticated player left or was kicked. Sent exactly once per `Connected`,
+    /// so it is the place to release per-player state.
+    Disconnected(SessionId, DisconnectReason, String),
+}
 
-use crate::{connection::Connection, motd::Motd};
 pub struct Listener {
-    socket: Arc<Mutex<Server>>,
-    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    socket: Option<Server>,
+    motd: Motd,
+    slots: Arc<Slots>,
+    config: Arc<Config>,
+    limiter: Arc<Limiter>,
+    events: mpsc::UnboundedReceiver<Event>,
+    event_sender: Option<mpsc::UnboundedSender<Event>>,
+    shutdown: Option<mpsc::UnboundedSender<String>>,
+    task: Option<JoinHandle<()>>,
 }
 
 impl Listener {
-    pub async fn new(mut motd: Motd, address: SocketAddr) -> Self {
-        let ret = Self {
-            socket: Arc::new(Mutex::new(Server::new(address, "".to_owned()))),
-            connections: Arc::new(Mutex::new(HashMap::new())),
-        };
-        motd.guid = ret.socket.lock().await.id;
-        ret.socket
-            .lock()
-            .await
+    pub async fn new(motd: Motd, address: SocketAddr) -> std::io::Result<Self> {
+        Self::with_config(motd, address, Config::default()).await
+    }
+
+    pub async fn with_config(
+        mut motd: Motd,
+        address: SocketAddr,
+        config: Config,
+    ) -> std::io::Result<Self> {
+        let (event_sender, events) = mpsc::unbounded_channel();
+        let mut socket = Server::new(address, "".to_owned());
+        motd.guid = socket.id;
+        motd.online_player = 0;
+        socket
             .set_motd(motd.to_string())
             .await
-            .unwrap();
-        ret
+            .map_err(raknet_error)?;
+        Ok(Self {
+            socket: Some(socket),
+            slots: Arc::new(Slots::new(motd.max_player)),
+            motd,
+            limiter: Arc::new(Limiter::new(config.rate_limits)),
+            config: Arc::new(config),
+            events,
+            event_sender: Some(event_sender),
+            shutdown: None,
+            task: None,
+        })
     }
-    pub async fn listen(&mut self) {
-        self.socket.lock().await.listen().await.unwrap();
-        let socket = self.socket.clone();
-        let connections = self.connections.clone();
-        tokio::spawn(async move {
+
+    /// Starts the socket task. Every RakNet session gets its own connection task; the socket
+    /// task only routes datagrams between them and RakNet.
+    pub async fn listen(&mut self) -> std::io::Result<()> {
+        let (mut socket, event_sender) = match (self.socket.take(), self.event_sender.take()) {
+            (Some(s), Some(e)) => (s, e),
+            _ => return Ok(()), // already listening
+        };
+        socket.listen().await.map_err(raknet_error)?;
+        let (shutdown_sender, mut shutdown) = mpsc::unbounded_channel::<String>();
+        self.shutdown = Some(shutdown_sender);
+        let mut motd = self.motd.clone();
+        let slots = self.slots.clone();
+        let config = self.config.clone();
+        let limiter = self.limiter.clone();
+        self.task = Some(tokio::spawn(async move {
+            let (outbound_sender, mut outbound) = mpsc::unbounded_channel();
+            let mut connections: HashMap<SocketAddr, Route> = HashMap::new();
+            // the kick message for every connection, including ones accepted during shutdown
+            let (closing, closing_receiver) = watch::channel(None);
+            let mut idle = false;
+            // set once shutdown starts; the task ends when every connection flushed or it passes
+            let mut deadline: Option<Instant> = None;
             loop {
-                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-                let events = socket.lock().await.recv().await.unwrap();
-                for event in events {
-                    match event {
-                        RaknetEvent::Packet(p) => {
-                            connections
-                                .lock()
-                                .await
-                                .get_mut(&p.address)
-                                .unwrap()
-                                .handle(p);
+                if deadline.is_some() && connections.is_empty() {
+                    break;
+                }
+                tokio::select! {
+                    biased;
+                    Some(message) = shutdown.recv(), if deadline.is_none() => {
+                        deadline = Some(Instant::now() + SHUTDOWN_TIMEOUT);
+                        let _ = closing.send(Some(message));
+                    }
+                    _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                        eprintln!("shutdown timed out with {} connections left", connections.len());
+                        break;
+                    }
+                    Some(out) = outbound.recv() => match out {
+                        Outbound::Send(address, buff) => {
+                            if let Err(e) = socket.send_to(&address, &buff).await {
+                                eprintln!("sending error {} for {}", e, address);
+                            }
                         }
-                        RaknetEvent::Connected(s, i) => {
-                            dbg!(i);
-                            connections
-                                .lock()
-                                .await
-                                .insert(s, Connection::new(socket.clone(), s));
+                        Outbound::Close(address) => {
+                            connections.remove(&address);
+                            if let Err(e) = socket.disconnect(&address).await {
+                                eprintln!("error while closing session {} {}", address, e);
+                            }
                         }
-                        RaknetEvent::Disconnected(s, _i, _r) => {
-                            connections.lock().await.get_mut(&s).unwrap().disconnected();
-                            connections.lock().await.remove(&s);
+                        Outbound::PlayerCount => {
+                            if motd.online_player != slots.online() {
+                                motd.online_player = slots.online();
+                                if let Err(e) = socket.set_motd(motd.to_string()).await {
+                                    eprintln!("error while updating motd {}", e);
+                                }
+                            }
                         }
-                        RaknetEvent::Error(s, e) => {
-                            connections.lock().await.remove(&s);
-                            eprintln!("Raknet Error : {}", e);
+                    },
+                    events = socket.recv(), if !idle => {
+                        let events = match events {
+                            Ok(p) => p,
+                            Err(e) => {
+                                eprintln!("Raknet Error : {}", e);
+                                vec![]
+                            }
+                        };
+                        idle = events.is_empty();
+                        for event in events {
+                            match event {
+                                RaknetEvent::Packet(p) => {
+                                    let route = match connections.get_mut(&p.address) {
+                                        Some(route) => route,
+                                        None => continue,
+                                    };
+                                    let now = Instant::now();
+                                    let amounts = [0, 0, p.data.len() as u64];
+                                    let message = match limiter.take(&mut route.buckets, p.address.ip(), amounts, now) {
+                                        Ok(wait) if wait.is_zero() => Inbound::Packet(p.data, None),
+                                        Ok(wait) => Inbound::Packet(p.data, Some(now + wait)),
+                                        Err(kind) => Inbound::OverLimit(kind),
+                                    };
+                                    // a connection that doesn't keep up loses batches instead
+                                    // of queueing them without limit
+                                    let _ = route.sender.try_send(message);
+                                }
+                                RaknetEvent::Connected(s, _i) => {
+                                    if deadline.is_some() || limiter.banned(s.ip()) {
+                                        let _ = socket.disconnect(&s).await;
+                                        continue;
+                                    }
+                                    let (sender, inbound) = mpsc::channel(INBOUND_QUEUE);
+                                    connections.insert(s, Route {
+                                        sender,
+                                        buckets: limiter.session(),
+                                    });
+                                    let conn = Connection::new(
+                                        s,
+                                        event_sender.clone(),
+                                        outbound_sender.clone(),
+                                        slots.clone(),
+                                        config.clone(),
+                                        limiter.clone(),
+                                    );
+                                    tokio::spawn(conn.run(inbound, closing_receiver.clone()));
+                                }
+                                RaknetEvent::Disconnected(s, _i, _r) => {
+                                    // a full queue ends without it, see `Connection::run`
+                                    if let Some(route) = connections.remove(&s) {
+                                        let _ = route.sender.try_send(Inbound::Disconnected(
+                                            DisconnectReason::Disconnected,
+                                            "connection closed".to_owned(),
+                                        ));
+                                    }
+                                }
+                                RaknetEvent::Error(s, e) => {
+                                    if let Some(route) = connections.remove(&s) {
+                                        let _ = route.sender.try_send(Inbound::Disconnected(
+                                            DisconnectReason::ConnectionLost,
+                                            "connection error".to_owned(),
+                                        ));
+                                    }
+                                    eprintln!("Raknet Error : {}", e);
+                                }
+                            }
                         }
                     }
+                    _ = tokio::time::sleep(IDLE_POLL), if idle => {
+                        idle = false;
+                    }
                 }
-                for conn in connections.lock().await.values_mut() {
-                    conn.update().await;
-                }
             }
-        });
+            for address in connections.keys() {
+                let _ = socket.disconnect(address).await;
+            }
+            // dropping the server releases the UDP socket
+        }));
+        Ok(())
     }
-    pub async fn recieve(&mut self) {
+
+    /// Stops accepting players, kicks everyone with `message` and waits for their `Disconnect`
+    /// to be flushed, then releases the socket. Gives up on slow connections after a few seconds.
+    pub async fn shutdown(&mut self, message: &str) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(message.to_owned());
+        }
+        if let Some(task) = self.task.take() {
+            if let Err(e) = task.await {
+                eprintln!("listener task failed {}", e);
+            }
+        }
+    }
+
+    /// Players holding a slot, the same number the MOTD advertises.
+    pub fn online_players(&self) -> u32 {
+        self.slots.online()
+    }
+
+    /// Waits for the next session event. Returns `None` once the listener task has stopped.
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.events.recv().await
     }
 }
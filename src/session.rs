@@ -0,0 +1,98 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::protocol::{
+    packets::{disconnect::DisconnectReason, encode_for, Packet},
+    types::player_data::{ExtraData, PlayerData},
+};
+
+pub(crate) enum Command {
+    Send(Vec<u8>), // encoded game packet, header included
+    Kick(u8, DisconnectReason, String),
+}
+
+/// Identifies a player: the RakNet connection, and the sub-client on it. Sub-client 0 is the
+/// player who opened the connection, 1 to 3 are split-screen players who joined through
+/// `SubClientLogin`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SessionId {
+    pub address: SocketAddr,
+    pub sub_client: u8,
+}
+
+/// Handle to an authenticated player. Cheap to clone; every clone talks to the same connection.
+#[derive(Clone)]
+pub struct Session {
+    id: SessionId,
+    protocol: u32,
+    identity: Arc<ExtraData>,
+    client_data: Arc<PlayerData>,
+    commands: UnboundedSender<Command>,
+}
+
+impl Session {
+    pub(crate) fn new(
+        id: SessionId,
+        protocol: u32,
+        identity: ExtraData,
+        client_data: PlayerData,
+        commands: UnboundedSender<Command>,
+    ) -> Self {
+        Self {
+            id,
+            protocol,
+            identity: Arc::new(identity),
+            client_data: Arc::new(client_data),
+            commands,
+        }
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.id.address
+    }
+
+    /// Protocol number the client logged in with. Packets from `Event::Packet` decode with it.
+    pub fn protocol(&self) -> u32 {
+        self.protocol
+    }
+
+    /// Verified identity from the login chain.
+    pub fn identity(&self) -> &ExtraData {
+        &self.identity
+    }
+
+    /// Skin and device data from the client data JWT.
+    pub fn client_data(&self) -> &PlayerData {
+        &self.client_data
+    }
+
+    pub fn send<T: Packet>(&self, packet: T) -> std::io::Result<()> {
+        let buff = encode_for::<T>(packet, self.protocol, 0, self.id.sub_client)?;
+        self.command(Command::Send(buff))
+    }
+
+    /// Sends `Disconnect` with the reason and message, then closes the session. Kicking the
+    /// primary player closes the connection along with its split-screen players.
+    pub fn kick(&self, reason: DisconnectReason, message: &str) -> std::io::Result<()> {
+        self.command(Command::Kick(
+            self.id.sub_client,
+            reason,
+            message.to_owned(),
+        ))
+    }
+
+    fn command(&self, command: Command) -> std::io::Result<()> {
+        match self.commands.send(command) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "connection is closed".to_owned(),
+            )),
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{Error, ErrorKind, Result},
+    path::Path,
+};
+
+use crate::{
+    nbt::{Compound, Tag, Variant},
+    protocol::version,
+    reader::{self, DecodeError, Reader},
+    writer::Writer,
+};
+
+/// Hashed runtime ID of `minecraft:unknown`, which vanilla doesn't hash.
+pub const UNKNOWN_HASH: u32 = 0xffff_fffe;
+
+/// 32-bit FNV-1a, as used for hashed block network IDs.
+pub fn fnv1a_32(data: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in data {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// 64-bit FNV-1, which vanilla orders block names by.
+pub fn fnv1_64(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        hash ^= *byte as u64;
+    }
+    hash
+}
+
+/// A block with its state values, e.g. `minecraft:stone_slab` with `top_slot_bit`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockState {
+    pub name: String,
+    pub states: Compound,
+    /// The version the state was last changed in, packed into an int.
+    pub version: i32,
+}
+
+// little-endian NBT of `{name, states}`, what vanilla hashes. Compounds write their keys
+// sorted, so equal states always give the same bytes. Fails on strings NBT can't hold.
+fn state_key(name: &str, states: &Compound) -> Result<Vec<u8>> {
+    let mut compound = Compound::new();
+    compound.insert("name".to_owned(), Tag::String(name.to_owned()));
+    compound.insert("states".to_owned(), Tag::Compound(states.clone()));
+    let mut cursor = Writer::new(vec![]);
+    Tag::Compound(compound).write(&mut cursor, Variant::LittleEndian, "")?;
+    Ok(cursor.get_raw_payload())
+}
+
+fn block_state(tag: Tag) -> reader::Result<BlockState> {
+    let invalid = |field| DecodeError::InvalidValue(format!("block state without {}", field));
+    Ok(BlockState {
+        name: match tag.get("name") {
+            Some(Tag::String(p)) => p.clone(),
+            _ => return Err(invalid("a name")),
+        },
+        states: match tag.get("states") {
+            Some(Tag::Compound(p)) => p.clone(),
+            _ => return Err(invalid("states")),
+        },
+        version: match tag.get("version") {
+            Some(Tag::Int(p)) => *p,
+            _ => 0,
+        },
+    })
+}
+
+/// The block states of one game version. Runtime IDs are either the index in vanilla order,
+/// blocks sorted by the FNV-1 hash of their name with each block's states in palette file
+/// order, or with block network ID hashing the FNV-1a hash of the state's `{name, states}`
+/// little-endian NBT.
+#[derive(Clone, Debug)]
+pub struct BlockRegistry {
+    states: Vec<BlockState>,
+    by_key: HashMap<Vec<u8>, u32>,
+    hashes: Vec<u32>,
+    by_hash: HashMap<u32, u32>,
+    air: u32,
+}
+
+impl BlockRegistry {
+    /// Reads a canonical block states file: network NBT compounds with a `name`, `states` and
+    /// `version`, one after another. It has to hold `minecraft:air`.
+    pub fn from_nbt(data: &[u8]) -> reader::Result<Self> {
+        let mut cursor = Reader::new(data);
+        let mut states = vec![];
+        while cursor.remaining() > 0 {
+            states.push(block_state(Tag::read(&mut cursor, Variant::Network)?.1)?);
+        }
+        Self::new(states)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::from_nbt(&fs::read(path)?)?)
+    }
+
+    /// Sorts the states into vanilla order, keeping the order of each block's states.
+    pub fn new(mut states: Vec<BlockState>) -> reader::Result<Self> {
+        states.sort_by_key(|p| fnv1_64(p.name.as_bytes()));
+        let mut by_key = HashMap::with_capacity(states.len());
+        let mut hashes = Vec::with_capacity(states.len());
+        let mut by_hash = HashMap::with_capacity(states.len());
+        for (i, state) in states.iter().enumerate() {
+            let key = match state_key(&state.name, &state.states) {
+                Ok(p) => p,
+                Err(e) => {
+                    return Err(DecodeError::InvalidValue(format!(
+                        "block state {}: {}",
+                        state.name, e
+                    )))
+                }
+            };
+            let hash = if state.name == "minecraft:unknown" {
+                UNKNOWN_HASH
+            } else {
+                fnv1a_32(&key)
+            };
+            if by_key.insert(key, i as u32).is_some() {
+                return Err(DecodeError::InvalidValue(format!(
+                    "block state {} {:?} listed twice",
+                    state.name, state.states
+                )));
+            }
+            if by_hash.insert(hash, i as u32).is_some() {
+                return Err(DecodeError::InvalidValue(format!(
+                    "block state {} {:?} hash collides",
+                    state.name, state.states
+                )));
+            }
+            hashes.push(hash);
+        }
+        let air = match states.iter().position(|p| p.name == "minecraft:air") {
+            Some(p) => p as u32,
+            None => {
+                return Err(DecodeError::InvalidValue(
+                    "block states without minecraft:air".to_owned(),
+                ))
+            }
+        };
+        Ok(Self {
+            states,
+            by_key,
+            hashes,
+            by_hash,
+            air,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// States in vanilla order, so indices are the unhashed runtime IDs.
+    pub fn states(&self) -> &[BlockState] {
+        &self.states
+    }
+
+    fn runtime_id_of(&self, index: u32, hashed: bool) -> u32 {
+        if hashed {
+            self.hashes[index as usize]
+        } else {
+            index
+        }
+    }
+
+    /// `None` for states not in the registry, including ones NBT can't hold.
+    pub fn runtime_id(&self, name: &str, states: &Compound, hashed: bool) -> Option<u32> {
+        let index = *self.by_key.get(&state_key(name, states).ok()?)?;
+        Some(self.runtime_id_of(index, hashed))
+    }
+
+    pub fn state(&self, runtime_id: u32, hashed: bool) -> Option<&BlockState> {
+        let index = if hashed {
+            *self.by_hash.get(&runtime_id)?
+        } else {
+            runtime_id
+        };
+        self.states.get(index as usize)
+    }
+
+    /// Runtime ID of `minecraft:air`, what empty sub-chunks are filled with.
+    pub fn air(&self, hashed: bool) -> u32 {
+        self.runtime_id_of(self.air, hashed)
+    }
+}
+
+/// Block registries by the protocol they were introduced in. A protocol uses the newest one
+/// not newer than itself, as block states only change in some releases.
+#[derive(Clone, Debug, Default)]
+pub struct BlockRegistries(BTreeMap<u32, BlockRegistry>);
+
+impl BlockRegistries {
+    pub fn insert(&mut self, protocol: u32, registry: BlockRegistry) {
+        self.0.insert(protocol, registry);
+    }
+
+    /// Loads `canonical_block_states_<protocol>.nbt` for each supported protocol that has one.
+    /// The vanilla palettes are not shipped with bers yet, they come from the server software
+    /// of each release.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        let mut registries = Self::default();
+        for (protocol, _) in version::SUPPORTED {
+            let path = dir.join(format!("canonical_block_states_{}.nbt", protocol));
+            if path.is_file() {
+                registries.insert(*protocol, BlockRegistry::load(path)?);
+            }
+        }
+        if registries.0.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no block states in {}", dir.display()),
+            ));
+        }
+        Ok(registries)
+    }
+
+    pub fn get(&self, protocol: u32) -> Option<&BlockRegistry> {
+        self.0.range(..=protocol).next_back().map(|(_, p)| p)
+    }
+}
+
+#[test]
+fn block_registry() {
+    assert_eq!(fnv1a_32(b""), 0x811c_9dc5);
+    assert_eq!(fnv1a_32(b"a"), 0xe40c_292c);
+    assert_eq!(fnv1_64(b"a"), 0xaf63_bd4c_8601_b7be);
+
+    let state = |name: &str, states: &[(&str, Tag)]| {
+        let mut compound = Compound::new();
+        compound.insert("name".to_owned(), Tag::String(name.to_owned()));
+        compound.insert(
+            "states".to_owned(),
+            Tag::Compound(
+                states
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+            ),
+        );
+        compound.insert("version".to_owned(), Tag::Int(18_090_528));
+        Tag::Compound(compound)
+    };
+    let mut cursor = Writer::new(vec![]);
+    for tag in [
+        state("minecraft:air", &[]),
+        state("minecraft:stone", &[]),
+        state("minecraft:stone_slab", &[("top_slot_bit", Tag::Byte(0))]),
+        state("minecraft:stone_slab", &[("top_slot_bit", Tag::Byte(1))]),
+        state("minecraft:unknown", &[]),
+    ] {
+        tag.write(&mut cursor, Variant::Network, "").unwrap();
+    }
+    let registry = BlockRegistry::from_nbt(&cursor.get_raw_payload()).unwrap();
+    assert_eq!(registry.len(), 5);
+    let names: Vec<&str> = registry.states().iter().map(|p| &p.name[..]).collect();
+    assert_eq!(
+        names,
+        [
+            "minecraft:stone_slab",
+            "minecraft:stone_slab",
+            "minecraft:stone",
+            "minecraft:unknown",
+            "minecraft:air",
+        ]
+    );
+    assert_eq!(registry.air(false), 4);
+    // the hashed air runtime ID vanilla sends
+    assert_eq!(registry.air(true) as i32, -604_749_536);
+
+    let mut top = Compound::new();
+    top.insert("top_slot_bit".to_owned(), Tag::Byte(1));
+    assert_eq!(
+        registry.runtime_id("minecraft:stone_slab", &top, false),
+        Some(1)
+    );
+    let hashed = registry
+        .runtime_id("minecraft:stone_slab", &top, true)
+        .unwrap();
+    assert_eq!(registry.state(hashed, true).unwrap().states, top);
+    assert_eq!(registry.state(1, false), registry.state(hashed, true));
+    assert_eq!(
+        registry.runtime_id("minecraft:unknown", &Compound::new(), true),
+        Some(UNKNOWN_HASH)
+    );
+    assert_eq!(registry.runtime_id("minecraft:stone", &top, false), None);
+    let long = "a".repeat(70_000);
+    assert_eq!(registry.runtime_id(&long, &top, false), None);
+    assert!(registry.state(5, false).is_none());
+
+    // a name too long for little-endian NBT is an error, not a panic
+    let mut cursor = Writer::new(vec![]);
+    for tag in [state("minecraft:air", &[]), state(&long, &[])] {
+        tag.write(&mut cursor, Variant::Network, "").unwrap();
+    }
+    match BlockRegistry::from_nbt(&cursor.get_raw_payload()) {
+        Err(DecodeError::InvalidValue(p)) => assert!(p.starts_with("block state aaa")),
+        p => panic!("{:?}", p.map(|p| p.len())),
+    }
+
+    let mut registries = BlockRegistries::default();
+    registries.insert(version::V1_19_80, registry);
+    assert!(registries.get(version::V1_19_70).is_none());
+    assert!(registries.get(version::V1_20_0).is_some());
+}
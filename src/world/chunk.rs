@@ -0,0 +1,479 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    ops::Range,
+};
+
+use crate::{
+    encoding::{Decode, Encode, VarI32},
+    nbt::Tag,
+    protocol::types::position::ChunkPos,
+    reader::{self, DecodeError, Reader},
+    writer::Writer,
+};
+
+/// Blocks in a sub-chunk, 16 on each side.
+pub const VOLUME: usize = 4096;
+
+/// The sub-chunk format written, 9 adds the sub-chunk index to 8.
+pub const SUB_CHUNK_VERSION: u8 = 9;
+
+// bits per palette index the client accepts, 0 is a single value without indices
+const BITS: [u8; 9] = [0, 1, 2, 3, 4, 5, 6, 8, 16];
+
+// header bits meaning "same as the storage before", only used for biomes
+const COPY_LAST: u8 = 0x7f;
+
+/// Sub-chunk indices of a dimension, bottom up. 0 is the overworld, 1 the nether and 2 the end.
+pub fn sub_chunk_range(dimension: i32) -> Range<i8> {
+    match dimension {
+        1 => 0..8,
+        2 => 0..16,
+        _ => -4..20,
+    }
+}
+
+fn offset(x: i32, y: i32, z: i32) -> usize {
+    ((x & 15) << 8 | (z & 15) << 4 | (y & 15)) as usize
+}
+
+fn word_count(bits: u8) -> usize {
+    if bits == 0 {
+        0
+    } else {
+        VOLUME.div_ceil(32 / bits as usize)
+    }
+}
+
+/// The 4096 values of a sub-chunk as indices into a palette, packed into u32 words without
+/// spanning two words. Values are block runtime IDs, or biome IDs for biome storage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PalettedStorage {
+    bits: u8,
+    words: Vec<u32>,
+    palette: Vec<u32>,
+}
+
+impl PalettedStorage {
+    /// A storage with every value the same.
+    pub fn uniform(value: u32) -> Self {
+        Self {
+            bits: 0,
+            words: vec![],
+            palette: vec![value],
+        }
+    }
+
+    pub fn bits_per_index(&self) -> u8 {
+        self.bits
+    }
+
+    /// May hold values no longer used, until it has to grow.
+    pub fn palette(&self) -> &[u32] {
+        &self.palette
+    }
+
+    fn index(&self, offset: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = 32 / self.bits as usize;
+        let shift = (offset % per_word) * self.bits as usize;
+        ((self.words[offset / per_word] >> shift) & ((1 << self.bits) - 1)) as usize
+    }
+
+    fn set_index(&mut self, offset: usize, index: usize) {
+        let per_word = 32 / self.bits as usize;
+        let shift = (offset % per_word) * self.bits as usize;
+        let word = &mut self.words[offset / per_word];
+        *word = *word & !(((1 << self.bits) - 1) << shift) | (index as u32) << shift;
+    }
+
+    /// Coordinates are taken modulo 16.
+    pub fn get(&self, x: i32, y: i32, z: i32) -> u32 {
+        self.palette[self.index(offset(x, y, z))]
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, z: i32, value: u32) {
+        let index = match self.palette.iter().position(|p| *p == value) {
+            Some(p) => p,
+            None => {
+                if self.palette.len() >= 1 << self.bits {
+                    self.grow();
+                }
+                self.palette.push(value);
+                self.palette.len() - 1
+            }
+        };
+        if self.bits != 0 {
+            self.set_index(offset(x, y, z), index);
+        }
+    }
+
+    // drops unused palette entries and repacks with room for one more
+    fn grow(&mut self) {
+        let indices: Vec<usize> = (0..VOLUME).map(|i| self.index(i)).collect();
+        let mut used = vec![false; self.palette.len()];
+        for index in &indices {
+            used[*index] = true;
+        }
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = vec![];
+        for (i, value) in self.palette.iter().enumerate() {
+            if used[i] {
+                remap[i] = palette.len();
+                palette.push(*value);
+            }
+        }
+        // at most 4096 are used, 16 bits always fit one more
+        self.bits = BITS.into_iter().find(|p| 1 << p > palette.len()).unwrap();
+        self.words = vec![0; word_count(self.bits)];
+        self.palette = palette;
+        if self.bits != 0 {
+            for (i, index) in indices.into_iter().enumerate() {
+                self.set_index(i, remap[index]);
+            }
+        }
+    }
+
+    pub fn is_uniform(&self, value: u32) -> bool {
+        (0..VOLUME).all(|i| self.palette[self.index(i)] == value)
+    }
+
+    /// Writes the runtime (network) form: a header byte, the words, then the palette as zigzag
+    /// varint32 with its length in front unless there is a single value. A storage equal to
+    /// `previous` is written as just a header.
+    pub fn write(&self, cursor: &mut Writer, previous: Option<&Self>) -> Result<()> {
+        if previous == Some(self) {
+            return cursor.write_u8(COPY_LAST << 1 | 1);
+        }
+        cursor.write_u8(self.bits << 1 | 1)?;
+        for word in &self.words {
+            word.encode(cursor)?;
+        }
+        if self.bits != 0 {
+            VarI32(self.palette.len() as i32).encode(cursor)?;
+        }
+        for value in &self.palette {
+            VarI32(*value as i32).encode(cursor)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(cursor: &mut Reader, previous: Option<&Self>) -> reader::Result<Self> {
+        let header = cursor.read_u8()?;
+        if header & 1 == 0 {
+            return Err(DecodeError::InvalidValue(
+                "persistent paletted storage in a network chunk".to_owned(),
+            ));
+        }
+        let bits = header >> 1;
+        if bits == COPY_LAST {
+            return match previous {
+                Some(p) => Ok(p.clone()),
+                None => Err(DecodeError::InvalidValue(
+                    "paletted storage copies a missing one".to_owned(),
+                )),
+            };
+        }
+        if !BITS.contains(&bits) {
+            return Err(DecodeError::InvalidValue(format!(
+                "{} bits per palette index",
+                bits
+            )));
+        }
+        let mut words = Vec::with_capacity(word_count(bits));
+        for _ in 0..word_count(bits) {
+            words.push(u32::decode(cursor)?);
+        }
+        let length = if bits == 0 {
+            1
+        } else {
+            let length = VarI32::decode(cursor)?.0;
+            if length <= 0 || length as u64 > 1 << bits {
+                return Err(DecodeError::InvalidValue(format!(
+                    "palette of {} for {} bits",
+                    length, bits
+                )));
+            }
+            length as usize
+        };
+        let mut palette = Vec::with_capacity(length.min(cursor.remaining()));
+        for _ in 0..length {
+            palette.push(VarI32::decode(cursor)?.0 as u32);
+        }
+        let storage = Self {
+            bits,
+            words,
+            palette,
+        };
+        if (0..VOLUME).any(|i| storage.index(i) >= length) {
+            return Err(DecodeError::InvalidValue(
+                "palette index out of range".to_owned(),
+            ));
+        }
+        Ok(storage)
+    }
+}
+
+/// 16×16×16 blocks in storage layers. Layer 0 holds the blocks, layer 1 mostly water in
+/// waterlogged blocks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubChunk {
+    air: u32,
+    pub layers: Vec<PalettedStorage>,
+}
+
+impl SubChunk {
+    /// An empty sub-chunk, `air` being the air runtime ID of the session's block registry.
+    pub fn new(air: u32) -> Self {
+        Self {
+            air,
+            layers: vec![PalettedStorage::uniform(air)],
+        }
+    }
+
+    /// Air for layers the sub-chunk doesn't have.
+    pub fn block(&self, x: i32, y: i32, z: i32, layer: usize) -> u32 {
+        match self.layers.get(layer) {
+            Some(p) => p.get(x, y, z),
+            None => self.air,
+        }
+    }
+
+    /// Adds layers up to `layer` as needed.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, layer: usize, runtime_id: u32) {
+        while self.layers.len() <= layer {
+            self.layers.push(PalettedStorage::uniform(self.air));
+        }
+        self.layers[layer].set(x, y, z, runtime_id);
+    }
+
+    /// Only air in every layer.
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(|p| p.is_uniform(self.air))
+    }
+
+    /// Writes the version 9 format with its sub-chunk index.
+    pub fn write(&self, cursor: &mut Writer, index: i8) -> Result<()> {
+        let layers = match u8::try_from(self.layers.len()) {
+            Ok(p) => p,
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "more than 255 storage layers",
+                ))
+            }
+        };
+        cursor.write_u8(SUB_CHUNK_VERSION)?;
+        cursor.write_u8(layers)?;
+        cursor.write_u8(index as u8)?;
+        for layer in &self.layers {
+            layer.write(cursor, None)?;
+        }
+        Ok(())
+    }
+
+    /// Reads version 8 or 9, with the index version 9 has.
+    pub fn read(cursor: &mut Reader, air: u32) -> reader::Result<(Self, Option<i8>)> {
+        let version = cursor.read_u8()?;
+        if version != 8 && version != 9 {
+            return Err(DecodeError::InvalidValue(format!(
+                "sub-chunk version {}",
+                version
+            )));
+        }
+        let count = cursor.read_u8()?;
+        let index = match version {
+            9 => Some(cursor.read_u8()? as i8),
+            _ => None,
+        };
+        let mut layers = Vec::with_capacity(count.into());
+        for _ in 0..count {
+            layers.push(PalettedStorage::read(cursor, None)?);
+        }
+        Ok((Self { air, layers }, index))
+    }
+}
+
+/// A chunk column. Block and biome coordinates are world ones: `x` and `z` are taken modulo
+/// 16, `y` outside the chunk's sub-chunks reads air or the lowest biome and is not set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    pub position: ChunkPos,
+    /// Index of the lowest sub-chunk, -4 in the overworld.
+    pub min_sub_chunk: i8,
+    /// Bottom up.
+    pub sub_chunks: Vec<SubChunk>,
+    /// The 3D biomes of each sub-chunk, biome IDs in place of runtime IDs.
+    pub biomes: Vec<PalettedStorage>,
+    /// Education edition border block columns, sent as is.
+    pub border_blocks: Vec<u8>,
+    /// Block entity compounds, each with its `x`, `y` and `z`.
+    pub block_entities: Vec<Tag>,
+    air: u32,
+}
+
+impl Chunk {
+    /// An empty chunk with one biome.
+    pub fn new(position: ChunkPos, sub_chunks: Range<i8>, air: u32, biome: u32) -> Self {
+        let count = sub_chunks.len();
+        Self {
+            position,
+            min_sub_chunk: sub_chunks.start,
+            sub_chunks: vec![SubChunk::new(air); count],
+            biomes: vec![PalettedStorage::uniform(biome); count],
+            border_blocks: vec![],
+            block_entities: vec![],
+            air,
+        }
+    }
+
+    fn sub_chunk_index(&self, y: i32) -> Option<usize> {
+        usize::try_from((y >> 4) - self.min_sub_chunk as i32)
+            .ok()
+            .filter(|p| *p < self.sub_chunks.len())
+    }
+
+    pub fn block(&self, x: i32, y: i32, z: i32, layer: usize) -> u32 {
+        match self.sub_chunk_index(y) {
+            Some(p) => self.sub_chunks[p].block(x, y, z, layer),
+            None => self.air,
+        }
+    }
+
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, layer: usize, runtime_id: u32) {
+        if let Some(p) = self.sub_chunk_index(y) {
+            self.sub_chunks[p].set_block(x, y, z, layer, runtime_id);
+        }
+    }
+
+    pub fn biome(&self, x: i32, y: i32, z: i32) -> u32 {
+        let index = self.sub_chunk_index(y).unwrap_or(0);
+        self.biomes[index].get(x, y, z)
+    }
+
+    pub fn set_biome(&mut self, x: i32, y: i32, z: i32, biome: u32) {
+        if let Some(p) = self.sub_chunk_index(y) {
+            self.biomes[p].set(x, y, z, biome);
+        }
+    }
+
+    /// The `LevelChunk` payload and its sub-chunk count: the sub-chunks up to the highest one
+    /// that isn't empty, the biomes of all of them, the border blocks and the block entities
+    /// as network NBT.
+    pub fn network_encode(&self) -> Result<(u32, Vec<u8>)> {
+        let count = match self.sub_chunks.iter().rposition(|p| !p.is_empty()) {
+            Some(p) => p + 1,
+            None => 0,
+        };
+        let mut cursor = Writer::new(vec![]);
+        for (i, sub_chunk) in self.sub_chunks[..count].iter().enumerate() {
+            sub_chunk.write(&mut cursor, self.min_sub_chunk + i as i8)?;
+        }
+        let mut previous = None;
+        for biome in &self.biomes {
+            biome.write(&mut cursor, previous)?;
+            previous = Some(biome);
+        }
+        let border_blocks = match u8::try_from(self.border_blocks.len()) {
+            Ok(p) => p,
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "more than 255 border blocks",
+                ))
+            }
+        };
+        cursor.write_u8(border_blocks)?;
+        cursor.write(&self.border_blocks)?;
+        for block_entity in &self.block_entities {
+            block_entity.encode(&mut cursor)?;
+        }
+        Ok((count as u32, cursor.get_raw_payload()))
+    }
+
+    /// Reads a `LevelChunk` payload with `count` sub-chunks.
+    pub fn network_decode(
+        position: ChunkPos,
+        sub_chunks: Range<i8>,
+        air: u32,
+        count: u32,
+        payload: &[u8],
+    ) -> reader::Result<Self> {
+        let total = sub_chunks.len();
+        if count as usize > total {
+            return Err(DecodeError::InvalidValue(format!(
+                "{} sub-chunks out of {}",
+                count, total
+            )));
+        }
+        let mut cursor = Reader::new(payload);
+        let mut chunk = Self::new(position, sub_chunks, air, 0);
+        for i in 0..count as usize {
+            let (sub_chunk, index) = SubChunk::read(&mut cursor, air)?;
+            let expected = chunk.min_sub_chunk + i as i8;
+            if index.is_some_and(|p| p != expected) {
+                return Err(DecodeError::InvalidValue(format!(
+                    "sub-chunk {} in place of {}",
+                    index.unwrap(),
+                    expected
+                )));
+            }
+            chunk.sub_chunks[i] = sub_chunk;
+        }
+        for i in 0..total {
+            let previous = i.checked_sub(1).map(|p| &chunk.biomes[p]);
+            chunk.biomes[i] = PalettedStorage::read(&mut cursor, previous)?;
+        }
+        let border_blocks = cursor.read_u8()?;
+        chunk.border_blocks = cursor.read_bytes(border_blocks.into())?.to_vec();
+        while cursor.remaining() > 0 {
+            chunk.block_entities.push(Tag::decode(&mut cursor)?);
+        }
+        Ok(chunk)
+    }
+}
+
+#[test]
+fn chunk_round_trip() {
+    let mut storage = PalettedStorage::uniform(0);
+    for i in 0..300 {
+        storage.set(i % 16, i / 16 % 16, i / 256, i as u32 + 1);
+    }
+    assert_eq!(storage.bits_per_index(), 16);
+    assert_eq!(storage.get(5, 3, 1), 0);
+    assert_eq!(storage.get(5, 3, 0), 54);
+    assert_eq!(storage.get(11, 2, 1), 300);
+    assert_eq!(storage.get(15, 15, 15), 0);
+    // overwriting frees palette entries, growing drops them
+    for i in 0..300 {
+        storage.set(i % 16, i / 16 % 16, i / 256, 7);
+    }
+    storage.grow();
+    assert_eq!(storage.palette(), [0, 7]);
+    assert_eq!(storage.bits_per_index(), 2);
+    assert!(!storage.is_uniform(7));
+
+    let air = 12;
+    let mut chunk = Chunk::new(ChunkPos { x: 3, z: -2 }, sub_chunk_range(0), air, 1);
+    chunk.set_block(0, -64, 0, 0, 5);
+    chunk.set_block(15, 70, 15, 1, 6);
+    chunk.set_block(0, 1000, 0, 0, 5);
+    chunk.set_biome(4, 0, 4, 2);
+    let mut block_entity = crate::nbt::Compound::new();
+    block_entity.insert("id".to_owned(), Tag::String("Chest".to_owned()));
+    chunk.block_entities.push(Tag::Compound(block_entity));
+    assert_eq!(chunk.block(0, -64, 0, 0), 5);
+    assert_eq!(chunk.block(15, 70, 15, 0), air);
+    assert_eq!(chunk.block(15, 70, 15, 1), 6);
+    assert_eq!(chunk.block(0, 1000, 0, 0), air);
+    assert_eq!(chunk.biome(4, 0, 4), 2);
+    assert_eq!(chunk.biome(4, 16, 4), 1);
+
+    let (count, payload) = chunk.network_encode().unwrap();
+    // 70 is in sub-chunk 4, the 9th from -4
+    assert_eq!(count, 9);
+    let decoded =
+        Chunk::network_decode(chunk.position, sub_chunk_range(0), air, count, &payload).unwrap();
+    assert_eq!(decoded, chunk);
+}
@@ -0,0 +1,4 @@
+//! In-memory world data and its network encoding.
+
+pub mod block;
+pub mod chunk;
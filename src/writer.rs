@@ -43,6 +43,18 @@ impl Writer {
             Endian::Little => self.cursor.write_i32::<LittleEndian>(v),
         }
     }
+    pub fn write_f32(&mut self, v: f32, n: Endian) -> Result<()> {
+        match n {
+            Endian::Big => self.cursor.write_f32::<BigEndian>(v),
+            Endian::Little => self.cursor.write_f32::<LittleEndian>(v),
+        }
+    }
+    pub fn write_f64(&mut self, v: f64, n: Endian) -> Result<()> {
+        match n {
+            Endian::Big => self.cursor.write_f64::<BigEndian>(v),
+            Endian::Little => self.cursor.write_f64::<LittleEndian>(v),
+        }
+    }
     pub fn write_u24(&mut self, v: u32, n: Endian) -> Result<()> {
         match n {
             Endian::Big => self.cursor.write_u24::<BigEndian>(v),
@@ -64,8 +76,9 @@ impl Writer {
         }
     }
 
+    /// Zigzag encoded, like `Reader::read_vari32`.
     pub fn write_vari32(&mut self, v: i32) -> Result<usize> {
-        self.cursor.write_var_i32(v)
+        self.cursor.write_var_u32(((v << 1) ^ (v >> 31)) as u32)
     }
 
     pub fn write_varu32(&mut self, v: u32) -> Result<usize> {
@@ -73,19 +86,26 @@ impl Writer {
     }
 
     pub fn write_vari64(&mut self, v: i64) -> Result<usize> {
-        self.cursor.write_var_i64(v)
+        self.cursor.write_var_u64(((v << 1) ^ (v >> 63)) as u64)
     }
 
     pub fn write_varu64(&mut self, v: u64) -> Result<usize> {
         self.cursor.write_var_u64(v)
     }
 
+    /// String with a varuint32 length, read back by `Reader::read_string`.
     pub fn write_string(&mut self, str: &str) -> Result<()> {
         let str_len = str.len() as u32;
         self.write_varu32(str_len)?;
         self.write(str.as_bytes())
     }
 
+    /// String with a u32 LE length, read back by `Reader::read_le_string`.
+    pub fn write_le_string(&mut self, str: &str) -> Result<()> {
+        self.write_u32(str.len() as u32, Endian::Little)?;
+        self.write(str.as_bytes())
+    }
+
     pub fn get_raw_payload(self) -> Vec<u8> {
         self.cursor.into_inner()
     }
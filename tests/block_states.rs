@@ -0,0 +1,29 @@
+use bers::{
+    nbt::{Compound, Tag},
+    protocol::version,
+    world::block::BlockRegistries,
+};
+
+// the vanilla palettes, once they are shipped under `data/block_states`
+#[test]
+#[ignore = "the vanilla block palettes are not shipped yet"]
+fn vanilla_block_states() {
+    let registries =
+        BlockRegistries::load_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/data/block_states"))
+            .unwrap();
+    let mut stone = Compound::new();
+    stone.insert("stone_type".to_owned(), Tag::String("stone".to_owned()));
+    for (protocol, _) in version::SUPPORTED {
+        let registry = registries.get(*protocol).unwrap();
+        // the hashed air runtime ID vanilla sends
+        assert_eq!(registry.air(true) as i32, -604_749_536);
+        let air = registry.state(registry.air(false), false).unwrap();
+        assert_eq!(air.name, "minecraft:air");
+        for hashed in [false, true] {
+            let id = registry
+                .runtime_id("minecraft:stone", &stone, hashed)
+                .unwrap();
+            assert_eq!(registry.state(id, hashed).unwrap().states, stone);
+        }
+    }
+}